@@ -0,0 +1,31 @@
+# layer-console shell integration for bash, source it from ~/.bashrc.
+# reports prompts and commands to the console with OSC 6 current file URIs
+# of the host `layer-console`, VTE not passing OSC 133 on to applications.
+# the usual OSC 133 marks are sent too, for other terminals only.
+
+if [[ $- == *i* && -z $__layer_console_integration ]]; then
+    __layer_console_integration=1
+    __layer_console_sequence=0
+    __layer_console_commands=0
+
+    __layer_console_precmd() {
+        local status=$?
+        printf '\e]133;D;%s\a\e]133;A\a' "$status"
+        # prompts set by other tools are rebuilt before each command
+        if [[ $PS1 != "$__layer_console_ps1" ]]; then
+            __layer_console_base_ps1=$PS1
+        fi
+        local prompt=${__layer_console_base_ps1//\\n/$'\n'}
+        local newlines=${prompt//[^$'\n']/}
+        local report
+        printf -v report '\e]6;file://layer-console/%s/prompt/%s/%s/%s\a' \
+            $((++__layer_console_sequence)) "$__layer_console_commands" \
+            $((${#newlines} + 1)) "$status"
+        PS1="$__layer_console_base_ps1\[\e]133;B\a$report\]"
+        __layer_console_ps1=$PS1
+        return $status
+    }
+
+    PROMPT_COMMAND="__layer_console_precmd${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+    PS0+='\e]133;C\a\e]6;file://layer-console/$((++__layer_console_sequence))/command/$((++__layer_console_commands))\a'
+fi
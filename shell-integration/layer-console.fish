@@ -0,0 +1,37 @@
+# layer-console shell integration for fish, source it from ~/.config/fish/config.fish.
+# reports prompts and commands to the console with OSC 6 current file URIs
+# of the host `layer-console`, VTE not passing OSC 133 on to applications.
+# the usual OSC 133 marks are sent too, for other terminals only.
+
+if status is-interactive; and not set -q __layer_console_integration
+    set -g __layer_console_integration 1
+    set -g __layer_console_sequence 0
+    set -g __layer_console_commands 0
+    set -g __layer_console_status
+
+    function __layer_console_prompt --on-event fish_prompt
+        printf '\e]133;A\a'
+    end
+
+    function __layer_console_preexec --on-event fish_preexec
+        set -g __layer_console_sequence (math $__layer_console_sequence + 1)
+        set -g __layer_console_commands (math $__layer_console_commands + 1)
+        printf '\e]133;C\a\e]6;file://layer-console/%s/command/%s\a' \
+            $__layer_console_sequence $__layer_console_commands
+    end
+
+    function __layer_console_postexec --on-event fish_postexec
+        set -g __layer_console_status $status
+        printf '\e]133;D;%s\a' $__layer_console_status
+    end
+
+    functions -c fish_prompt __layer_console_fish_prompt
+    function fish_prompt
+        set -l prompt (__layer_console_fish_prompt | string collect)
+        printf '%s' $prompt
+        set -g __layer_console_sequence (math $__layer_console_sequence + 1)
+        printf '\e]133;B\a\e]6;file://layer-console/%s/prompt/%s/%s/%s\a' \
+            $__layer_console_sequence $__layer_console_commands \
+            (count (string split \n -- $prompt)) $__layer_console_status
+    end
+end
@@ -1,25 +1,395 @@
 use gtk::prelude::*;
-use gtk::{gio, glib};
-use serde::Deserialize;
+use gtk::{gdk, gio, glib};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 use crate::layer_console;
+use crate::link;
+use crate::util;
 use crate::G_LOG_DOMAIN;
 
 const CONFIG_DIR_NAME: &str = "layer-console";
 const CONFIG_FILE_NAME: &str = "config.toml";
+const DROP_IN_EXTENSION: &str = "d";
 
-#[derive(Debug, Default, Deserialize)]
+/// configuration of layer-console
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(title = "layer-console", extend("additionalProperties" = false))]
 pub struct Config {
-    pub shell: Option<String>,
+    pub shell: Option<Shell>,
+    /// run the shell as a login shell, `-` starting its argv[0]
+    pub login_shell: Option<bool>,
+    /// directory the shells start in, `~` and variables are expanded
     pub working_directory: Option<String>,
+    /// rows of the terminal
+    #[schemars(range(min = 1))]
     pub rows: Option<i64>,
+    /// columns of the terminal
+    #[schemars(range(min = 1))]
     pub columns: Option<i64>,
+    /// font, like `Monospace 13`
     pub font: Option<String>,
     pub position: Option<Position>,
     pub keyboard_mode: Option<KeyboardMode>,
+    /// copy the selected text to the clipboard
+    pub copy_on_select: Option<bool>,
+    /// paste the primary selection with a middle click
+    pub middle_click_paste: Option<bool>,
+    /// ask before pasting multi-line text or text containing control characters
+    pub confirm_paste: Option<bool>,
+    /// extra patterns opened with ctrl+click
+    #[serde(default)]
+    pub matchers: Vec<Matcher>,
+    /// command opening links instead of the default application, `$1` being the link
+    pub opener: Option<String>,
+    /// commands opening links of a given scheme, overriding `opener`, `$2` being the
+    /// host of the link with its user and `$3` its port, run in a new tab with `tab:`
+    #[serde(default)]
+    pub url_handlers: HashMap<String, String>,
+    /// hide the console after opening a link
+    pub hide_on_open: Option<bool>,
+    pub link_modifier: Option<LinkModifier>,
+    /// command opening file paths, `$1` being the path, `$2` the line, `$3` the column
+    pub editor: Option<String>,
+    pub cursor_shape: Option<CursorShape>,
+    pub cursor_blink: Option<CursorBlink>,
+    /// color of the cursor, like `#d8d8d8`
+    pub cursor_color: Option<String>,
+    /// color of the text under the cursor
+    pub cursor_foreground: Option<String>,
+    pub bell: Option<Bell>,
+    /// notify when a command finishes or the bell rings while hidden
+    pub notify_when_hidden: Option<bool>,
+    /// seconds a command runs for at least to be notified
+    pub notify_min_duration: Option<u32>,
+    /// save the consoles while running and bring them back on the next start
+    pub restore_session: Option<bool>,
+    /// run again the commands of the restored consoles instead of a shell
+    pub restore_commands: Option<bool>,
+    /// keep the scrollback of the consoles in the session when quitting
+    pub save_scrollback: Option<bool>,
+    /// KiB of the end of each scrollback to keep
+    pub scrollback_save_limit: Option<u32>,
+    /// run the processes in `layer-console --daemon`, which keeps them running
+    /// when the console goes away and gives them back to the next one
+    pub daemon: Option<bool>,
+    /// tmux session attached in control mode (`tmux -C`, not the `-CC` of
+    /// iTerm2), its windows being the tabs and its panes splits
+    pub tmux: Option<String>,
+    /// refuse to start a console when the config file has errors, instead
+    /// of leaving the settings they concern to their default
+    pub strict: Option<bool>,
+    /// tabs opened when the console starts, a single shell if empty
+    #[serde(default)]
+    pub tabs: Vec<Tab>,
+    /// files merged under this one, relative to its directory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// named sets of keys overriding the ones above, selected with `--profile`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub profiles: HashMap<String, Config>,
 }
 
-#[derive(Debug, Deserialize)]
+/// `merge`, `with_defaults` and `changed_keys` of `Config`, from its keys
+/// holding a single value, which a later file replaces, with their default if
+/// they have one. the other keys are lists, handled one by one.
+macro_rules! single_value_keys {
+    ($($key:ident $(= $default:expr)?,)*) => {
+        #[cfg(test)]
+        const SINGLE_VALUE_KEYS: &[&str] = &[$(stringify!($key)),*];
+
+        impl Config {
+            /// keys set in `other` take precedence, matchers and url handlers are merged,
+            /// tabs are replaced
+            pub fn merge(mut self, other: Config) -> Config {
+                $(self.$key = other.$key.or(self.$key);)*
+                self.matchers.extend(other.matchers);
+                self.url_handlers.extend(other.url_handlers);
+                if !other.tabs.is_empty() {
+                    self.tabs = other.tabs;
+                }
+                self.include.extend(other.include);
+                self.profiles.extend(other.profiles);
+                self
+            }
+
+            /// the config with the keys having a default set to it
+            pub fn with_defaults(mut self) -> Config {
+                $($(
+                    if self.$key.is_none() {
+                        self.$key = Some($default);
+                    }
+                )?)*
+                self.url_handlers
+                    .entry("ssh".to_string())
+                    .or_insert_with(|| layer_console::DEFAULT_SSH_HANDLER.to_string());
+                self
+            }
+
+            /// names of the keys with another value in `other`, profiles aside
+            pub fn changed_keys(&self, other: &Config) -> Vec<&'static str> {
+                let mut keys = Vec::new();
+                $(
+                    if self.$key != other.$key {
+                        keys.push(stringify!($key));
+                    }
+                )*
+                if self.matchers != other.matchers {
+                    keys.push("matchers");
+                }
+                if self.url_handlers != other.url_handlers {
+                    keys.push("url_handlers");
+                }
+                if self.tabs != other.tabs {
+                    keys.push("tabs");
+                }
+                keys
+            }
+        }
+    };
+}
+
+single_value_keys! {
+    shell = Shell::Program(util::get_user_shell()),
+    login_shell = false,
+    working_directory,
+    rows = layer_console::DEFAULT_ROWS,
+    columns = layer_console::DEFAULT_COLUMNS,
+    font = layer_console::DEFAULT_FONT.to_string(),
+    position = Position::Top,
+    keyboard_mode = KeyboardMode::OnDemand,
+    copy_on_select = false,
+    middle_click_paste = true,
+    confirm_paste = true,
+    opener,
+    hide_on_open = false,
+    link_modifier = LinkModifier::Ctrl,
+    editor,
+    cursor_shape = CursorShape::Block,
+    cursor_blink = CursorBlink::System,
+    cursor_color,
+    cursor_foreground,
+    bell = Bell::Audible,
+    notify_when_hidden = false,
+    notify_min_duration = layer_console::DEFAULT_NOTIFY_MIN_DURATION,
+    restore_session = false,
+    restore_commands = false,
+    save_scrollback = false,
+    scrollback_save_limit = layer_console::DEFAULT_SCROLLBACK_SAVE_LIMIT,
+    daemon = false,
+    tmux,
+    strict = false,
+}
+
+impl Config {
+    /// the configuration of the profile `name`, None if it is not defined
+    pub fn with_profile(mut self, name: &str) -> Option<Config> {
+        let mut profile = self.profiles.remove(name)?;
+        if !profile.profiles.is_empty() {
+            glib::g_warning!(
+                G_LOG_DOMAIN,
+                "profiles can't be nested, ignoring the ones in profile `{}`",
+                name
+            );
+            profile.profiles.clear();
+        }
+        Some(self.merge(profile))
+    }
+
+    /// expand `~` and the variables in the paths, in the profiles too,
+    /// returning the variables which are unset
+    pub fn expand_paths(&mut self) -> Vec<String> {
+        let mut unset = Vec::new();
+        // the arguments are the program's to interpret
+        let program = match &mut self.shell {
+            Some(Shell::Program(program)) => Some(program),
+            Some(Shell::Command(args)) => args.first_mut(),
+            None => None,
+        };
+        let paths = program
+            .into_iter()
+            .chain(&mut self.working_directory)
+            .chain(self.include.iter_mut())
+            .chain(
+                self.tabs
+                    .iter_mut()
+                    .filter_map(|tab| tab.working_directory.as_mut()),
+            );
+        for path in paths {
+            *path = util::expand_path(path, &mut unset);
+        }
+        for profile in self.profiles.values_mut() {
+            for name in profile.expand_paths() {
+                if !unset.contains(&name) {
+                    unset.push(name);
+                }
+            }
+        }
+        unset
+    }
+
+    /// what keeps the settings from working, past the syntax of the file
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        match self.shell.as_ref().map(Shell::args).as_deref() {
+            Some([]) => problems.push("shell has no program".to_string()),
+            Some([program, ..]) if glib::find_program_in_path(program).is_none() => {
+                problems.push(format!("shell `{}` not found", program));
+            }
+            _ => (),
+        }
+        if let Some(directory) = &self.working_directory {
+            if !std::path::Path::new(directory).is_dir() {
+                problems.push(format!(
+                    "working_directory `{}` is not a directory",
+                    directory
+                ));
+            }
+        }
+        for (key, value) in [("rows", self.rows), ("columns", self.columns)] {
+            if let Some(value) = value.filter(|value| *value <= 0) {
+                problems.push(format!("`{}` must be positive, not {}", key, value));
+            }
+        }
+        if let Some(font) = &self.font {
+            let description = gtk::pango::FontDescription::from_string(font);
+            // pango leaves the size unset when it can't read it, as in `Monospace 0`
+            let has_size = font
+                .split_whitespace()
+                .last()
+                .is_some_and(|word| word.starts_with(|c: char| c.is_ascii_digit()));
+            if description.family().is_none_or(|family| family.is_empty()) {
+                problems.push(format!("font `{}` has no family", font));
+            } else if has_size && description.size() <= 0 {
+                problems.push(format!("font `{}` has an invalid size", font));
+            }
+        }
+        for (key, color) in [
+            ("cursor_color", &self.cursor_color),
+            ("cursor_foreground", &self.cursor_foreground),
+        ] {
+            if let Some(Err(e)) = color.as_deref().map(gdk::RGBA::parse) {
+                problems.push(format!(
+                    "invalid {} `{}`: {}",
+                    key,
+                    color.as_deref().unwrap_or_default(),
+                    e
+                ));
+            }
+        }
+        for matcher in &self.matchers {
+            if matcher.as_template().is_none() {
+                problems.push(format!(
+                    "matcher `{}` needs exactly one of `url` or `command`",
+                    matcher.regex
+                ));
+            }
+            if let Err(e) = glib::Regex::new(
+                &matcher.regex,
+                glib::RegexCompileFlags::MULTILINE,
+                glib::RegexMatchFlags::DEFAULT,
+            ) {
+                problems.push(format!("invalid matcher regex `{}`: {}", matcher.regex, e));
+            }
+        }
+        for tab in &self.tabs {
+            if let Some(Err(e)) = tab.args() {
+                problems.push(format!(
+                    "invalid tab command `{}`: {}",
+                    tab.command.as_deref().unwrap_or_default(),
+                    e
+                ));
+            }
+            if let Some(directory) = &tab.working_directory {
+                if !std::path::Path::new(directory).is_dir() {
+                    problems.push(format!(
+                        "tab working_directory `{}` is not a directory",
+                        directory
+                    ));
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// user defined pattern turned into a link when clicked, with either a url or a command
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("additionalProperties" = false))]
+pub struct Matcher {
+    /// pattern turned into a link, `$1`... being its groups
+    pub regex: String,
+    /// url opened
+    pub url: Option<String>,
+    /// command run by /bin/sh in the shell's current directory
+    pub command: Option<String>,
+}
+
+impl Matcher {
+    pub fn as_template(&self) -> Option<link::Template> {
+        match (&self.url, &self.command) {
+            (Some(url), None) => Some(link::Template::Url(url.clone())),
+            (None, Some(command)) => Some(link::Template::Command(command.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// program started in new tabs, alone or with its arguments
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Shell {
+    Program(String),
+    Command(Vec<String>),
+}
+
+impl Shell {
+    /// the program followed by its arguments
+    pub fn args(&self) -> Vec<String> {
+        match self {
+            Shell::Program(program) => vec![program.clone()],
+            Shell::Command(args) => args.clone(),
+        }
+    }
+}
+
+/// tab opened when the console starts
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("additionalProperties" = false))]
+pub struct Tab {
+    /// label of the tab, the title of the terminal if unset
+    pub title: Option<String>,
+    /// command line run instead of the shell, split like a shell would
+    pub command: Option<String>,
+    /// directory the command starts in
+    pub working_directory: Option<String>,
+    /// variables added to the environment of the command
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
+
+impl Tab {
+    /// arguments of the command, None to run the shell
+    pub fn args(&self) -> Option<Result<Vec<String>, glib::Error>> {
+        let command = self.command.as_ref()?;
+        Some(glib::shell_parse_argv(command).map(|args| {
+            args.iter()
+                .map(|arg| arg.to_string_lossy().to_string())
+                .collect()
+        }))
+    }
+    /// the environment as `NAME=VALUE` entries
+    pub fn env(&self) -> Vec<String> {
+        self.env
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect()
+    }
+}
+
+/// edge of the screen the console is on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum Position {
     Top,
@@ -39,8 +409,12 @@ impl Position {
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// whether the console takes the keyboard when shown,
+/// `ondemand` and `on-demand` being aliases of `on_demand`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
+// schemars leaves out the aliases
+#[schemars(extend("enum" = ["exclusive", "on_demand", "ondemand", "on-demand"]))]
 pub enum KeyboardMode {
     Exclusive,
     #[serde(rename = "on_demand", alias = "ondemand", alias = "on-demand")]
@@ -56,7 +430,93 @@ impl KeyboardMode {
     }
 }
 
-fn default_config_path() -> std::path::PathBuf {
+/// modifier to hold while clicking a link, `control` being an alias of `ctrl`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+#[schemars(extend("enum" = ["none", "ctrl", "control", "shift", "alt"]))]
+pub enum LinkModifier {
+    None,
+    #[serde(alias = "control")]
+    Ctrl,
+    Shift,
+    Alt,
+}
+
+impl LinkModifier {
+    pub fn as_modifier_type(&self) -> gdk::ModifierType {
+        match self {
+            LinkModifier::None => gdk::ModifierType::empty(),
+            LinkModifier::Ctrl => gdk::ModifierType::CONTROL_MASK,
+            LinkModifier::Shift => gdk::ModifierType::SHIFT_MASK,
+            LinkModifier::Alt => gdk::ModifierType::ALT_MASK,
+        }
+    }
+}
+
+/// shape of the cursor, `beam` being an alias of `ibeam`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+#[schemars(extend("enum" = ["block", "ibeam", "beam", "underline"]))]
+pub enum CursorShape {
+    Block,
+    #[serde(alias = "beam")]
+    Ibeam,
+    Underline,
+}
+
+impl CursorShape {
+    pub fn as_cursor_shape(&self) -> vte4::CursorShape {
+        match self {
+            CursorShape::Block => vte4::CursorShape::Block,
+            CursorShape::Ibeam => vte4::CursorShape::Ibeam,
+            CursorShape::Underline => vte4::CursorShape::Underline,
+        }
+    }
+}
+
+/// whether the cursor blinks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CursorBlink {
+    System,
+    On,
+    Off,
+}
+
+impl CursorBlink {
+    pub fn as_cursor_blink_mode(&self) -> vte4::CursorBlinkMode {
+        match self {
+            CursorBlink::System => vte4::CursorBlinkMode::System,
+            CursorBlink::On => vte4::CursorBlinkMode::On,
+            CursorBlink::Off => vte4::CursorBlinkMode::Off,
+        }
+    }
+}
+
+/// what the bell does, `urgent` marking the console until it is focused
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Bell {
+    None,
+    Audible,
+    Visual,
+    Urgent,
+    Notify,
+}
+
+impl Bell {
+    pub fn as_bell(&self) -> layer_console::Bell {
+        match self {
+            Bell::None => layer_console::Bell::None,
+            Bell::Audible => layer_console::Bell::Audible,
+            Bell::Visual => layer_console::Bell::Visual,
+            Bell::Urgent => layer_console::Bell::Urgent,
+            Bell::Notify => layer_console::Bell::Notify,
+        }
+    }
+}
+
+pub fn default_config_path() -> std::path::PathBuf {
     let mut config_path = glib::user_config_dir();
     config_path.push(CONFIG_DIR_NAME);
     config_path.push(CONFIG_FILE_NAME);
@@ -64,42 +524,472 @@ fn default_config_path() -> std::path::PathBuf {
     config_path
 }
 
-pub fn load_config(config_path: Option<std::path::PathBuf>) -> Config {
+/// the directory of the files merged over the config file at `path`,
+/// `config.d` for `config.toml`
+pub fn drop_in_dir(path: &std::path::Path) -> std::path::PathBuf {
+    path.with_extension(DROP_IN_EXTENSION)
+}
+
+/// the config file with what is wrong in it, which is also logged
+pub fn load_config(config_path: Option<std::path::PathBuf>) -> (Config, Vec<String>) {
+    let (config, errors) =
+        read_config(config_path).unwrap_or_else(|e| (Default::default(), vec![e]));
+    for error in &errors {
+        glib::g_warning!(G_LOG_DOMAIN, "config file: {}", error);
+    }
+    (config, errors)
+}
+
+/// the config file without the keys which can't be used, with the errors
+/// about them, or an error when nothing can be read from the file.
+/// the default config is used when the default file doesn't exist.
+pub fn read_config(
+    config_path: Option<std::path::PathBuf>,
+) -> Result<(Config, Vec<String>), String> {
+    let (config, sources) = read_config_sources(config_path)?;
+    Ok((config, sources.errors))
+}
+
+/// the files the config file at `path` is made of: itself, the files it
+/// includes and the drop-in files, even the ones which can't be read
+pub fn config_files(path: &std::path::Path) -> Vec<std::path::PathBuf> {
+    match read_config_sources(Some(path.to_path_buf())) {
+        Ok((_, sources)) => sources.files,
+        Err(_) => vec![path.to_path_buf()],
+    }
+}
+
+/// the config file with the files it includes under it and the drop-in
+/// files over it, in lexical order
+fn read_config_sources(
+    config_path: Option<std::path::PathBuf>,
+) -> Result<(Config, Sources), String> {
     let explicit_path = config_path.is_some();
     let config_path = config_path.unwrap_or_else(default_config_path);
+    let mut sources = Sources::default();
+    sources.files.push(config_path.clone());
+    let mut config = match read_config_file(&config_path, explicit_path)? {
+        Some(text) => sources.parse(&config_path, &text, true)?,
+        None => Default::default(),
+    };
 
-    let config_file = gio::File::for_path(&config_path);
-
-    let data = match config_file.load_bytes(gio::Cancellable::NONE) {
+    let dir = drop_in_dir(&config_path);
+    let mut drop_ins = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
         Err(e) => {
-            if explicit_path {
-                glib::g_warning!(G_LOG_DOMAIN, "can't read config file: {}", e);
-            }
-            return Default::default();
+            sources
+                .errors
+                .push(format!("can't read {}: {}", dir.display(), e));
+            Vec::new()
         }
-        Ok((data, _)) => data,
     };
+    drop_ins.sort();
+    for path in drop_ins {
+        config = config.merge(sources.include(&path));
+    }
+    Ok((config, sources))
+}
 
-    let text = match std::str::from_utf8(&data) {
-        Err(e) => {
+/// the files read for a config, with the errors found in them
+#[derive(Default)]
+struct Sources {
+    files: Vec<std::path::PathBuf>,
+    errors: Vec<String>,
+}
+
+impl Sources {
+    /// the config in `text`, read from `path`, over the files it includes.
+    /// the errors of the files other than the main one start with their path.
+    fn parse(&mut self, path: &std::path::Path, text: &str, main: bool) -> Result<Config, String> {
+        let (mut config, errors) = parse_config(text)?;
+        // the settings still apply, the path may just not exist
+        for name in config.expand_paths() {
             glib::g_warning!(
                 G_LOG_DOMAIN,
-                "failed to read config file as utf-8 string: {}",
-                e
+                "{}: `${}` is not set, it is left as is",
+                path.display(),
+                name
             );
+        }
+        self.errors
+            .extend(errors.into_iter().map(|error| match main {
+                true => error,
+                false => format!("{}: {}", path.display(), error),
+            }));
+        let mut names = config.profiles.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let profile = config.profiles.get_mut(&name).unwrap();
+            if !profile.include.is_empty() {
+                self.errors
+                    .push(format!("profile `{}`: profiles can't include files", name));
+                profile.include.clear();
+            }
+        }
+        let dir = path.parent().unwrap_or(std::path::Path::new("."));
+        let mut included = Config::default();
+        for include in std::mem::take(&mut config.include) {
+            included = included.merge(self.include(&dir.join(include)));
+        }
+        Ok(included.merge(config))
+    }
+
+    /// the config in the file at `path`, empty if it can't be read
+    fn include(&mut self, path: &std::path::Path) -> Config {
+        // a file included twice, or in a cycle, is read once
+        if self.files.iter().any(|file| file == path) {
             return Default::default();
         }
-        Ok(text) => text,
+        self.files.push(path.to_path_buf());
+        let text = match read_config_file(path, true) {
+            Ok(text) => text.unwrap_or_default(),
+            Err(e) => {
+                self.errors.push(e);
+                return Default::default();
+            }
+        };
+        self.parse(path, &text, false).unwrap_or_else(|e| {
+            self.errors.push(format!("{}: {}", path.display(), e));
+            Default::default()
+        })
+    }
+}
+
+/// the text of the config file, None if it doesn't exist and isn't `explicit_path`
+fn read_config_file(
+    config_path: &std::path::Path,
+    explicit_path: bool,
+) -> Result<Option<String>, String> {
+    let config_file = gio::File::for_path(config_path);
+
+    let data = match config_file.load_bytes(gio::Cancellable::NONE) {
+        Err(e) if !explicit_path && e.matches(gio::IOErrorEnum::NotFound) => return Ok(None),
+        Err(e) => return Err(format!("can't read config file: {}", e)),
+        Ok((data, _)) => data,
     };
 
+    let text = std::str::from_utf8(&data)
+        .map_err(|e| format!("failed to read config file as utf-8 string: {}", e))?;
+    Ok(Some(text.to_string()))
+}
+
+/// line and column of the byte at `offset` in `text`, from 1
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// where the values of the keys start in the text of the config file
+struct Spans<'a> {
+    text: &'a str,
+    keys: HashMap<String, std::ops::Range<usize>>,
+    profiles: HashMap<String, HashMap<String, std::ops::Range<usize>>>,
+}
+
+#[derive(Deserialize)]
+struct ProfileSpans {
+    #[serde(default)]
+    profiles: HashMap<String, HashMap<String, toml::Spanned<toml::Value>>>,
+}
+
+impl<'a> Spans<'a> {
+    fn new(text: &'a str) -> Self {
+        let keys = toml::from_str::<HashMap<String, toml::Spanned<toml::Value>>>(text)
+            .map(|keys| {
+                keys.into_iter()
+                    .map(|(key, value)| (key, value.span()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let profiles = toml::from_str::<ProfileSpans>(text)
+            .map(|spans| {
+                spans
+                    .profiles
+                    .into_iter()
+                    .map(|(name, keys)| {
+                        let keys = keys
+                            .into_iter()
+                            .map(|(key, value)| (key, value.span()))
+                            .collect();
+                        (name, keys)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Spans {
+            text,
+            keys,
+            profiles,
+        }
+    }
+    /// `message` about the key at `path`, like `profiles.work.font`,
+    /// with where its value is
+    fn describe(&self, path: &str, message: &str) -> String {
+        let mut keys = path.split('.');
+        let span = match (keys.next(), keys.next(), keys.next()) {
+            (Some("profiles"), Some(name), Some(key)) => self
+                .profiles
+                .get(name)
+                .and_then(|keys| keys.get(key))
+                .or_else(|| self.keys.get("profiles")),
+            (Some(key), _, _) => self.keys.get(key),
+            _ => None,
+        };
+        match span {
+            Some(span) => {
+                let (line, column) = position(self.text, span.start);
+                format!(
+                    "`{}` at line {}, column {}: {}",
+                    path, line, column, message
+                )
+            }
+            None => format!("`{}`: {}", path, message),
+        }
+    }
+}
+
+/// the error deserializing a config holding only `key`
+fn check_key(key: &str, value: toml::Value) -> Result<(), toml::de::Error> {
+    let mut table = toml::Table::new();
+    table.insert(key.to_string(), value);
+    Config::deserialize(toml::Value::Table(table)).map(|_| ())
+}
+
+/// the keys of `table` which can be used, the others being reported in `errors`.
+/// lists of tables, like the matchers, keep their valid entries.
+fn valid_keys(
+    table: toml::Table,
+    prefix: &str,
+    spans: &Spans,
+    errors: &mut Vec<String>,
+) -> toml::Table {
+    let mut valid = toml::Table::new();
+    for (key, value) in table {
+        let path = format!("{}{}", prefix, key);
+        let value = match value {
+            toml::Value::Table(profiles) if key == "profiles" && prefix.is_empty() => {
+                toml::Value::Table(
+                    profiles
+                        .into_iter()
+                        .map(|(name, profile)| match profile {
+                            toml::Value::Table(profile) => {
+                                let prefix = format!("profiles.{}.", name);
+                                let profile = valid_keys(profile, &prefix, spans, errors);
+                                (name, toml::Value::Table(profile))
+                            }
+                            profile => (name, profile),
+                        })
+                        .collect(),
+                )
+            }
+            toml::Value::Array(items) if items.iter().all(toml::Value::is_table) => {
+                toml::Value::Array(
+                    items
+                        .into_iter()
+                        .enumerate()
+                        .filter_map(|(i, item)| {
+                            match check_key(&key, toml::Value::Array(vec![item.clone()])) {
+                                Ok(()) => Some(item),
+                                Err(e) => {
+                                    let message = format!("entry {}: {}", i + 1, e.message());
+                                    errors.push(spans.describe(&path, &message));
+                                    None
+                                }
+                            }
+                        })
+                        .collect(),
+                )
+            }
+            value => value,
+        };
+        match check_key(&key, value.clone()) {
+            Ok(()) => {
+                valid.insert(key, value);
+            }
+            Err(e) => errors.push(spans.describe(&path, e.message())),
+        }
+    }
+    valid
+}
+
+/// parse the config, skipping the keys which can't be used, or fail if
+/// the file isn't valid TOML
+fn parse_config(text: &str) -> Result<(Config, Vec<String>), String> {
+    let spans = Spans::new(text);
+    let mut errors = Vec::new();
     let d = toml::de::Deserializer::new(text);
-    match serde_ignored::deserialize(d, |path| {
-        glib::g_warning!(G_LOG_DOMAIN, "unknown key in config file: `{}`", path)
-    }) {
+    let mut unknown_keys = Vec::new();
+    if let Ok(config) = serde_ignored::deserialize(d, |path| unknown_keys.push(path.to_string())) {
+        for path in unknown_keys {
+            errors.push(spans.describe(&path, "unknown key"));
+        }
+        return Ok((config, errors));
+    }
+    // find out which keys are wrong to keep the other ones
+    let table = toml::from_str::<toml::Table>(text).map_err(|e| {
+        let (line, column) = position(text, e.span().map_or(0, |span| span.start));
+        format!("line {}, column {}: {}", line, column, e.message())
+    })?;
+    let table = valid_keys(table, "", &spans, &mut errors);
+    let config = serde_ignored::deserialize(toml::Value::Table(table), |path| {
+        errors.push(spans.describe(&path.to_string(), "unknown key"));
+    })
+    .map_err(|e| e.to_string())?;
+    Ok((config, errors))
+}
+
+/// every problem found in the config file and its profiles, empty if it is fine
+pub fn check_config(config_path: Option<std::path::PathBuf>) -> Vec<String> {
+    let (mut config, mut problems) = match read_config(config_path) {
         Ok(config) => config,
-        Err(e) => {
-            glib::g_warning!(G_LOG_DOMAIN, "failed to parse config file: {}", e);
-            Default::default()
+        Err(e) => return vec![e],
+    };
+    let base_problems = config.problems();
+    let profiles = std::mem::take(&mut config.profiles);
+    let mut names = profiles.keys().cloned().collect::<Vec<_>>();
+    names.sort();
+    problems.extend(base_problems.iter().cloned());
+    for name in names {
+        let mut profile = profiles[&name].clone();
+        if !profile.profiles.is_empty() {
+            problems.push(format!("profile `{}`: profiles can't be nested", name));
+            profile.profiles.clear();
+        }
+        // the problems of the base config were already reported
+        for problem in config.clone().merge(profile).problems() {
+            if !base_problems.contains(&problem) {
+                problems.push(format!("profile `{}`: {}", name, problem));
+            }
         }
     }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_value_keeps_other_keys() {
+        let (config, errors) = parse_config("rows = \"x\"\nfont = \"Monospace 12\"\n").unwrap();
+        assert_eq!(config.rows, None);
+        assert_eq!(config.font.as_deref(), Some("Monospace 12"));
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].starts_with("`rows` at line 1, column 8: invalid type"),
+            "{}",
+            errors[0]
+        );
+    }
+
+    #[test]
+    fn bad_matcher_keeps_other_matchers() {
+        let text = r##"
+[[matchers]]
+regex = "JIRA-[0-9]+"
+url = "https://jira.example.com/browse/$0"
+
+[[matchers]]
+regex = 1
+
+[[matchers]]
+regex = "#[0-9]+"
+command = "gh issue view $0"
+"##;
+        let (config, errors) = parse_config(text).unwrap();
+        let regexes = config
+            .matchers
+            .iter()
+            .map(|matcher| matcher.regex.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(regexes, ["JIRA-[0-9]+", "#[0-9]+"]);
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].starts_with("`matchers`") && errors[0].contains("entry 2: "),
+            "{}",
+            errors[0]
+        );
+    }
+
+    #[test]
+    fn bad_profile_key_has_position() {
+        let text = r#"font = "Monospace 12"
+
+[profiles.x]
+columns = 100
+rows = "x"
+"#;
+        let (config, errors) = parse_config(text).unwrap();
+        assert_eq!(config.font.as_deref(), Some("Monospace 12"));
+        let profile = &config.profiles["x"];
+        assert_eq!(profile.columns, Some(100));
+        assert_eq!(profile.rows, None);
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].starts_with("`profiles.x.rows` at line 5, column 8: invalid type"),
+            "{}",
+            errors[0]
+        );
+    }
+
+    #[test]
+    fn unknown_profile_key_has_position() {
+        let text = "[profiles.x]\ncolums = 100\n";
+        let (config, errors) = parse_config(text).unwrap();
+        assert!(config.profiles.contains_key("x"));
+        assert_eq!(
+            errors,
+            ["`profiles.x.colums` at line 2, column 10: unknown key"]
+        );
+    }
+
+    #[test]
+    fn every_key_is_merged() {
+        let schema = serde_json::from_str::<serde_json::Value>(&crate::schema::config_schema());
+        let schema = schema.unwrap();
+        let mut keys = schema["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let lists = ["matchers", "url_handlers", "tabs", "include", "profiles"];
+        let mut merged = SINGLE_VALUE_KEYS
+            .iter()
+            .chain(&lists)
+            .copied()
+            .collect::<Vec<_>>();
+        keys.sort();
+        merged.sort();
+        assert_eq!(keys, merged);
+    }
+
+    #[test]
+    fn invalid_font_size_is_a_problem() {
+        let config = |font: &str| Config {
+            font: Some(font.to_string()),
+            ..Config::default()
+        };
+        assert_eq!(config("Monospace 13").problems(), Vec::<String>::new());
+        assert_eq!(config("Monospace").problems(), Vec::<String>::new());
+        assert_eq!(
+            config("Monospace 0").problems(),
+            ["font `Monospace 0` has an invalid size"]
+        );
+    }
+
+    #[test]
+    fn invalid_toml_fails() {
+        let error = parse_config("rows = 10\nfont = \n").unwrap_err();
+        assert!(error.starts_with("line 2, column "), "{}", error);
+    }
 }
@@ -0,0 +1,741 @@
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use std::cell::{Cell, OnceCell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::util;
+use crate::G_LOG_DOMAIN;
+
+const RUNTIME_DIR_NAME: &str = "layer-console";
+const SOCKET_FILE_NAME: &str = "daemon.sock";
+/// output kept while no console is attached, replayed to the next one
+const BACKLOG_LIMIT: usize = 1024 * 1024;
+const READ_SIZE: usize = 64 * 1024;
+/// how long to wait for a daemon we started to listen
+const START_TIMEOUT: Duration = Duration::from_secs(2);
+const START_RETRY_DELAY: Duration = Duration::from_millis(20);
+/// how long to wait for the pty of a session
+const FD_TIMEOUT: Duration = Duration::from_secs(5);
+
+// the daemon and the consoles exchange one message per line, each being
+// a (name, payload) variant in the GVariant text format. once a session
+// is spawned or attached the console asks for "fd" and receives a copy of
+// the pty, which it reads and writes directly.
+
+fn message_type() -> &'static glib::VariantTy {
+    glib::VariantTy::new("(sv)").unwrap()
+}
+
+fn encode(name: &str, payload: glib::Variant) -> String {
+    format!("{}\n", (name, payload).to_variant().print(true))
+}
+
+fn decode(line: &str) -> Result<(String, glib::Variant), String> {
+    let message = glib::Variant::parse(Some(message_type()), line)
+        .map_err(|e| format!("invalid message: {}", e))?;
+    message
+        .get::<(String, glib::Variant)>()
+        .ok_or_else(|| "invalid message".to_string())
+}
+
+fn send(connection: &gio::SocketConnection, name: &str, payload: glib::Variant) {
+    let message = encode(name, payload);
+    if let Err(e) = connection
+        .output_stream()
+        .write_all(message.as_bytes(), gio::Cancellable::NONE)
+    {
+        glib::g_warning!(G_LOG_DOMAIN, "failed to write to the socket: {}", e);
+    }
+}
+
+pub fn socket_path() -> PathBuf {
+    let mut path = glib::user_runtime_dir();
+    path.push(RUNTIME_DIR_NAME);
+    path.push(SOCKET_FILE_NAME);
+    path
+}
+
+fn connect() -> Result<gio::SocketConnection, glib::Error> {
+    SocketClientExt::connect(
+        &gio::SocketClient::new(),
+        &gio::UnixSocketAddress::new(&socket_path()),
+        gio::Cancellable::NONE,
+    )
+}
+
+fn connect_async<F: FnOnce(Result<gio::SocketConnection, glib::Error>) + 'static>(f: F) {
+    gio::SocketClient::new().connect_async(
+        &gio::UnixSocketAddress::new(&socket_path()),
+        gio::Cancellable::NONE,
+        f,
+    );
+}
+
+/// process running in the daemon with the pty it runs on
+struct Session {
+    pty: vte4::Pty,
+    command: Vec<String>,
+    env: Vec<String>,
+    login_shell: bool,
+    /// output read while no console is attached
+    backlog: VecDeque<u8>,
+    read_source: Option<glib::SourceId>,
+    /// connection of the console reading the pty, told when the process exits
+    client: Option<gio::SocketConnection>,
+}
+
+impl Session {
+    /// read the pty into the backlog until a console attaches
+    fn detach(&mut self, id: u64) {
+        self.client = None;
+        if self.read_source.is_some() {
+            return;
+        }
+        self.read_source = Some(glib::unix_fd_add_local(
+            self.pty.fd().as_raw_fd(),
+            glib::IOCondition::IN | glib::IOCondition::HUP | glib::IOCondition::ERR,
+            move |fd, condition| read_backlog(id, fd, condition),
+        ));
+    }
+    fn attach(&mut self, client: &gio::SocketConnection) -> Vec<u8> {
+        if let Some(source) = self.read_source.take() {
+            source.remove();
+        }
+        self.client = Some(client.clone());
+        self.backlog.drain(..).collect()
+    }
+    /// drop the start of the backlog past its limit, up to the end of a line
+    fn trim_backlog(&mut self) {
+        let excess = self.backlog.len().saturating_sub(BACKLOG_LIMIT);
+        if excess == 0 {
+            return;
+        }
+        let line_end = self
+            .backlog
+            .iter()
+            .skip(excess)
+            .position(|&byte| byte == b'\n')
+            .map_or(excess, |position| excess + position + 1);
+        self.backlog.drain(..line_end);
+    }
+}
+
+#[derive(Default)]
+struct Daemon {
+    sessions: HashMap<u64, Session>,
+    next_id: u64,
+}
+
+thread_local! {
+    static DAEMON: RefCell<Daemon> = RefCell::default();
+    static MAIN_LOOP: OnceCell<glib::MainLoop> = const { OnceCell::new() };
+}
+
+fn read_backlog(id: u64, fd: i32, condition: glib::IOCondition) -> glib::ControlFlow {
+    if condition.contains(glib::IOCondition::IN) {
+        let mut buffer = vec![0; READ_SIZE];
+        match nix::unistd::read(fd, &mut buffer) {
+            Ok(n) if n > 0 => {
+                DAEMON.with_borrow_mut(|daemon| {
+                    if let Some(session) = daemon.sessions.get_mut(&id) {
+                        session.backlog.extend(&buffer[..n]);
+                        session.trim_backlog();
+                    }
+                });
+                return glib::ControlFlow::Continue;
+            }
+            Err(nix::errno::Errno::EAGAIN | nix::errno::Errno::EINTR) => {
+                return glib::ControlFlow::Continue;
+            }
+            // EIO once the process closed its side
+            _ => {}
+        }
+    } else if !condition.intersects(glib::IOCondition::HUP | glib::IOCondition::ERR) {
+        return glib::ControlFlow::Continue;
+    }
+    DAEMON.with_borrow_mut(|daemon| {
+        if let Some(session) = daemon.sessions.get_mut(&id) {
+            session.read_source = None;
+        }
+    });
+    glib::ControlFlow::Break
+}
+
+/// forget the session, the daemon quits with the last one
+fn remove_session(id: u64) {
+    let empty = DAEMON.with_borrow_mut(|daemon| {
+        if let Some(mut session) = daemon.sessions.remove(&id) {
+            if let Some(source) = session.read_source.take() {
+                source.remove();
+            }
+        }
+        daemon.sessions.is_empty()
+    });
+    if empty {
+        MAIN_LOOP.with(|main_loop| {
+            if let Some(main_loop) = main_loop.get() {
+                main_loop.quit();
+            }
+        });
+    }
+}
+
+fn process_exited(id: u64, status: i32) {
+    let client = DAEMON.with_borrow_mut(|daemon| {
+        daemon
+            .sessions
+            .get_mut(&id)
+            .and_then(|session| session.client.take())
+    });
+    if let Some(client) = client {
+        send(&client, "exited", status.to_variant());
+    }
+    remove_session(id);
+}
+
+fn spawn_session(
+    command: Vec<String>,
+    directory: String,
+    env: Vec<String>,
+    login_shell: bool,
+    client: &gio::SocketConnection,
+) -> Result<u64, String> {
+    let pty = vte4::Pty::new_sync(vte4::PtyFlags::DEFAULT, gio::Cancellable::NONE)
+        .map_err(|e| format!("failed to open a pty: {}", e))?;
+    let id = DAEMON.with_borrow_mut(|daemon| {
+        daemon.next_id += 1;
+        daemon.next_id
+    });
+    let directory = Some(directory).filter(|directory| !directory.is_empty());
+    let (argv, flags) = util::spawn_args(
+        &command.iter().map(String::as_str).collect::<Vec<_>>(),
+        login_shell,
+    );
+    pty.spawn_async(
+        directory.as_deref(),
+        &argv.iter().map(String::as_str).collect::<Vec<_>>(),
+        &env.iter().map(String::as_str).collect::<Vec<_>>(),
+        flags,
+        || {},
+        -1,
+        gio::Cancellable::NONE,
+        move |result| match result {
+            Ok(pid) => {
+                glib::child_watch_add_local(pid, move |_pid, status| process_exited(id, status));
+            }
+            Err(e) => {
+                glib::g_warning!(G_LOG_DOMAIN, "failed to spawn: {}", e);
+                process_exited(id, -1);
+            }
+        },
+    );
+    DAEMON.with_borrow_mut(|daemon| {
+        daemon.sessions.insert(
+            id,
+            Session {
+                pty,
+                command,
+                env,
+                login_shell,
+                backlog: VecDeque::new(),
+                read_source: None,
+                client: Some(client.clone()),
+            },
+        );
+    });
+    Ok(id)
+}
+
+/// payload answering "spawn" and "attach"
+fn session_payload(id: u64, backlog: Vec<u8>) -> glib::Variant {
+    DAEMON.with_borrow(|daemon| {
+        let session = &daemon.sessions[&id];
+        (
+            id,
+            backlog,
+            session.command.clone(),
+            session.env.clone(),
+            session.login_shell,
+        )
+            .to_variant()
+    })
+}
+
+/// give the pty of the session to the console, alongside a single byte
+fn send_fd(client: &gio::SocketConnection, id: u64) -> Result<(), String> {
+    let pty = DAEMON
+        .with_borrow(|daemon| daemon.sessions.get(&id).map(|session| session.pty.clone()))
+        .ok_or("no session")?;
+    let message = gio::UnixFDMessage::new();
+    message.append_fd(pty.fd()).map_err(|e| e.to_string())?;
+    client
+        .socket()
+        .send_message(
+            None::<&gio::SocketAddress>,
+            &[gio::OutputVector::new(&[0])],
+            &[message.upcast()],
+            0,
+            gio::Cancellable::NONE,
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// handle one request of a console, `attached` being the session it follows
+fn handle_request(client: &gio::SocketConnection, line: &str, attached: &Cell<Option<u64>>) {
+    let (name, payload) = match decode(line) {
+        Ok(message) => message,
+        Err(e) => {
+            send(client, "error", e.to_variant());
+            return;
+        }
+    };
+    match name.as_str() {
+        "spawn" => {
+            let Some((command, directory, env, login_shell)) =
+                payload.get::<(Vec<String>, String, Vec<String>, bool)>()
+            else {
+                send(client, "error", "invalid spawn request".to_variant());
+                return;
+            };
+            match spawn_session(command, directory, env, login_shell, client) {
+                Ok(id) => {
+                    attached.set(Some(id));
+                    send(client, "session", session_payload(id, Vec::new()));
+                }
+                Err(e) => send(client, "error", e.to_variant()),
+            }
+        }
+        "attach" => {
+            let Some(id) = payload.get::<u64>() else {
+                send(client, "error", "invalid attach request".to_variant());
+                return;
+            };
+            let backlog = DAEMON.with_borrow_mut(|daemon| {
+                let session = daemon.sessions.get_mut(&id)?;
+                if session.client.is_some() {
+                    return None;
+                }
+                Some(session.attach(client))
+            });
+            match backlog {
+                Some(backlog) => {
+                    attached.set(Some(id));
+                    send(client, "session", session_payload(id, backlog));
+                }
+                None => send(
+                    client,
+                    "error",
+                    format!("no detached session {}", id).to_variant(),
+                ),
+            }
+        }
+        "fd" => {
+            let result = match attached.get() {
+                Some(id) => send_fd(client, id),
+                None => Err("no session".to_string()),
+            };
+            if let Err(e) = result {
+                glib::g_warning!(G_LOG_DOMAIN, "can't send the pty: {}", e);
+                // the console waits for the pty, tell it there is none
+                if let Err(e) = client.close(gio::Cancellable::NONE) {
+                    glib::g_warning!(G_LOG_DOMAIN, "failed to close the connection: {}", e);
+                }
+            }
+        }
+        "list" => {
+            let ids = DAEMON.with_borrow(|daemon| {
+                let mut ids = daemon
+                    .sessions
+                    .iter()
+                    .filter(|(_, session)| session.client.is_none())
+                    .map(|(id, _)| *id)
+                    .collect::<Vec<_>>();
+                ids.sort();
+                ids
+            });
+            send(client, "sessions", ids.to_variant());
+        }
+        "close" => {
+            if let Some(id) = attached.take() {
+                remove_session(id);
+            }
+        }
+        _ => send(
+            client,
+            "error",
+            format!("unknown request `{}`", name).to_variant(),
+        ),
+    }
+}
+
+fn read_requests(
+    client: gio::SocketConnection,
+    input: gio::DataInputStream,
+    attached: Rc<Cell<Option<u64>>>,
+) {
+    input.clone().read_line_utf8_async(
+        glib::Priority::DEFAULT,
+        gio::Cancellable::NONE,
+        move |result| match result {
+            Ok(Some(line)) => {
+                handle_request(&client, &line, &attached);
+                read_requests(client, input, attached);
+            }
+            // the console went away, keep its process running
+            Ok(None) | Err(_) => {
+                if let Some(id) = attached.get() {
+                    DAEMON.with_borrow_mut(|daemon| {
+                        if let Some(session) = daemon.sessions.get_mut(&id) {
+                            session.detach(id);
+                        }
+                    });
+                }
+            }
+        },
+    );
+}
+
+/// leave the session of the console, so that the processes don't get
+/// hung up on when it goes away
+fn leave_session() -> nix::Result<()> {
+    use nix::unistd::{fork, setsid, ForkResult};
+    match setsid() {
+        Ok(_) => Ok(()),
+        // a process group leader, as when started from a shell, can't but its child can
+        Err(nix::errno::Errno::EPERM) => {
+            // SAFETY: the daemon starts no thread before this
+            match unsafe { fork() }? {
+                ForkResult::Parent { .. } => std::process::exit(0),
+                ForkResult::Child => setsid().map(|_| ()),
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// run the daemon holding the processes of the consoles until none is left
+pub fn run() -> i32 {
+    if let Err(e) = leave_session() {
+        glib::g_warning!(
+            G_LOG_DOMAIN,
+            "can't leave the session of the console: {}",
+            e
+        );
+        return 1;
+    }
+    if connect().is_ok() {
+        glib::g_warning!(G_LOG_DOMAIN, "the daemon is already running");
+        return 0;
+    }
+    let path = socket_path();
+    if let Some(dir) = path.parent() {
+        use std::os::unix::fs::DirBuilderExt;
+        if let Err(e) = std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+        {
+            glib::g_warning!(G_LOG_DOMAIN, "can't create {}: {}", dir.display(), e);
+            return 1;
+        }
+    }
+    // left by a daemon which didn't quit properly
+    let _ = std::fs::remove_file(&path);
+    let service = gio::SocketService::new();
+    if let Err(e) = service.add_address(
+        &gio::UnixSocketAddress::new(&path),
+        gio::SocketType::Stream,
+        gio::SocketProtocol::Default,
+        None::<&glib::Object>,
+    ) {
+        glib::g_warning!(G_LOG_DOMAIN, "can't listen on {}: {}", path.display(), e);
+        return 1;
+    }
+    service.connect_incoming(|_service, connection, _source| {
+        let input = gio::DataInputStream::new(&connection.input_stream());
+        read_requests(connection.clone(), input, Rc::default());
+        false
+    });
+    service.start();
+    let main_loop = glib::MainLoop::new(None, false);
+    MAIN_LOOP.with(|cell| cell.set(main_loop.clone()).unwrap());
+    main_loop.run();
+    let _ = std::fs::remove_file(&path);
+    0
+}
+
+/// a process running in the daemon, followed by a console
+#[derive(Debug)]
+pub struct Client {
+    id: u64,
+    connection: gio::SocketConnection,
+    input: gio::DataInputStream,
+}
+
+/// what the daemon tells about a session when it is spawned or attached
+pub struct SessionInfo {
+    pub client: Client,
+    pub pty: vte4::Pty,
+    /// output of the process since the last console went away
+    pub backlog: Vec<u8>,
+    pub command: Vec<String>,
+    pub env: Vec<String>,
+    pub login_shell: bool,
+}
+
+impl Client {
+    /// connect to the daemon, starting it first if `start` and it isn't running
+    fn connect<F: FnOnce(Result<Client, String>) + 'static>(start: bool, f: F) {
+        connect_async(move |result| match result {
+            Ok(connection) => f(Ok(Client::new(connection))),
+            Err(e) if !start => f(Err(e.to_string())),
+            Err(_) => start_daemon(move |result| f(result.map(Client::new))),
+        });
+    }
+    fn new(connection: gio::SocketConnection) -> Client {
+        let input = gio::DataInputStream::new(&connection.input_stream());
+        Client {
+            id: 0,
+            connection,
+            input,
+        }
+    }
+    /// send a request, `f` getting back the client with the answer
+    fn request<F: FnOnce(Client, Result<glib::Variant, String>) + 'static>(
+        self,
+        name: &str,
+        payload: glib::Variant,
+        f: F,
+    ) {
+        if let Err(e) = self.send(name, payload) {
+            f(self, Err(e));
+            return;
+        }
+        self.input.clone().read_line_utf8_async(
+            glib::Priority::DEFAULT,
+            gio::Cancellable::NONE,
+            move |result| {
+                let answer = match result {
+                    Ok(Some(line)) => match decode(&line) {
+                        Ok((name, payload)) if name == "error" => {
+                            Err(payload.get::<String>().unwrap_or(name))
+                        }
+                        Ok((_, payload)) => Ok(payload),
+                        Err(e) => Err(e),
+                    },
+                    Ok(None) => Err("the daemon closed the connection".to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                f(self, answer);
+            },
+        );
+    }
+    fn send(&self, name: &str, payload: glib::Variant) -> Result<(), String> {
+        self.connection
+            .output_stream()
+            .write_all(encode(name, payload).as_bytes(), gio::Cancellable::NONE)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+    /// the session answered by the daemon, with its pty
+    fn session<F: FnOnce(Result<SessionInfo, String>) + 'static>(
+        mut self,
+        payload: glib::Variant,
+        f: F,
+    ) {
+        let Some((id, backlog, command, env, login_shell)) =
+            payload.get::<(u64, Vec<u8>, Vec<String>, Vec<String>, bool)>()
+        else {
+            f(Err("invalid session".to_string()));
+            return;
+        };
+        self.id = id;
+        if let Err(e) = self.send("fd", ().to_variant()) {
+            f(Err(e));
+            return;
+        }
+        let fd = self.connection.socket().as_raw_fd();
+        // whichever of the pty and the timeout comes first removes the other
+        let pending = Rc::new(RefCell::new(Some((self, f))));
+        let timeout: Rc<RefCell<Option<glib::SourceId>>> = Rc::default();
+        let watch = glib::unix_fd_add_local(
+            fd,
+            glib::IOCondition::IN | glib::IOCondition::HUP | glib::IOCondition::ERR,
+            glib::clone!(
+                #[strong]
+                pending,
+                #[strong]
+                timeout,
+                move |_fd, _condition| {
+                    if let Some(source) = timeout.take() {
+                        source.remove();
+                    }
+                    if let Some((client, f)) = pending.take() {
+                        let pty = client.receive_fd().and_then(|fd| {
+                            vte4::Pty::foreign_sync(fd, gio::Cancellable::NONE)
+                                .map_err(|e| e.to_string())
+                        });
+                        f(pty.map(|pty| SessionInfo {
+                            client,
+                            pty,
+                            backlog,
+                            command,
+                            env,
+                            login_shell,
+                        }));
+                    }
+                    glib::ControlFlow::Break
+                }
+            ),
+        );
+        let source = glib::timeout_add_local_once(FD_TIMEOUT, move || {
+            if let Some((_client, f)) = pending.take() {
+                watch.remove();
+                f(Err("the daemon didn't send the pty".to_string()));
+            }
+        });
+        timeout.replace(Some(source));
+    }
+    fn receive_fd(&self) -> Result<OwnedFd, String> {
+        let mut byte = [0];
+        let mut messages = gio::SocketControlMessages::new();
+        let (received, _flags) = self
+            .connection
+            .socket()
+            .receive_message(
+                None,
+                &mut [gio::InputVector::new(&mut byte)],
+                Some(&mut messages),
+                0,
+                gio::Cancellable::NONE,
+            )
+            .map_err(|e| e.to_string())?;
+        if received == 0 {
+            return Err("the daemon closed the connection".to_string());
+        }
+        let mut fds = messages
+            .iter()
+            .filter_map(|message| message.downcast_ref::<gio::UnixFDMessage>())
+            .flat_map(|message| message.steal_fds())
+            // SAFETY: the fds were just received, nothing else owns them
+            .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+        fds.next()
+            .ok_or_else(|| "the daemon sent no pty".to_string())
+    }
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    /// end the process, instead of leaving it to the next console
+    pub fn close(&self) {
+        if let Err(e) = self.send("close", ().to_variant()) {
+            glib::g_warning!(G_LOG_DOMAIN, "can't close session {}: {}", self.id, e);
+        }
+    }
+    /// call `f` with the exit status of the process once it exits
+    pub fn connect_exited<F: FnOnce(i32) + 'static>(&self, f: F) {
+        let id = self.id;
+        self.input.read_line_utf8_async(
+            glib::Priority::DEFAULT,
+            gio::Cancellable::NONE,
+            move |result| match result.map(|line| line.map(|line| decode(&line))) {
+                Ok(Some(Ok((name, status)))) if name == "exited" => {
+                    f(status.get::<i32>().unwrap_or(-1))
+                }
+                Ok(Some(_)) => {
+                    glib::g_warning!(G_LOG_DOMAIN, "unexpected message for session {}", id);
+                }
+                Ok(None) | Err(_) => {
+                    glib::g_warning!(G_LOG_DOMAIN, "lost the daemon running session {}", id);
+                }
+            },
+        );
+    }
+}
+
+/// start `layer-console --daemon` and connect to it once it listens
+fn start_daemon<F: FnOnce(Result<gio::SocketConnection, String>) + 'static>(f: F) {
+    let started = std::env::current_exe()
+        .map_err(|e| e.to_string())
+        .and_then(|exe| {
+            gio::Subprocess::newv(
+                &[exe.as_os_str(), "--daemon".as_ref()],
+                gio::SubprocessFlags::NONE,
+            )
+            .map_err(|e| format!("can't start the daemon: {}", e))
+        });
+    match started {
+        Ok(_) => connect_started_daemon(Instant::now(), f),
+        Err(e) => f(Err(e)),
+    }
+}
+
+/// try to connect to the daemon started at `started` until it listens
+fn connect_started_daemon<F: FnOnce(Result<gio::SocketConnection, String>) + 'static>(
+    started: Instant,
+    f: F,
+) {
+    connect_async(move |result| match result {
+        Ok(connection) => f(Ok(connection)),
+        Err(e) if started.elapsed() > START_TIMEOUT => {
+            f(Err(format!("can't connect to the daemon: {}", e)));
+        }
+        Err(_) => {
+            glib::timeout_add_local_once(START_RETRY_DELAY, move || {
+                connect_started_daemon(started, f)
+            });
+        }
+    });
+}
+
+/// run `command` in the daemon, as a login shell with `login_shell`,
+/// starting the daemon if needed
+pub fn spawn<F: FnOnce(Result<SessionInfo, String>) + 'static>(
+    command: &[&str],
+    directory: Option<&str>,
+    env: &[&str],
+    login_shell: bool,
+    f: F,
+) {
+    let payload = (command, directory.unwrap_or_default(), env, login_shell).to_variant();
+    Client::connect(true, move |client| match client {
+        Ok(client) => client.request("spawn", payload, |client, answer| match answer {
+            Ok(payload) => client.session(payload, f),
+            Err(e) => f(Err(e)),
+        }),
+        Err(e) => f(Err(e)),
+    });
+}
+
+/// follow a session no console is attached to
+pub fn attach<F: FnOnce(Result<SessionInfo, String>) + 'static>(id: u64, f: F) {
+    Client::connect(false, move |client| match client {
+        Ok(client) => client.request("attach", id.to_variant(), |client, answer| match answer {
+            Ok(payload) => client.session(payload, f),
+            Err(e) => f(Err(e)),
+        }),
+        Err(e) => f(Err(e)),
+    });
+}
+
+/// call `f` with the sessions no console is attached to, none if the
+/// daemon isn't running
+pub fn detached_sessions<F: FnOnce(Vec<u64>) + 'static>(f: F) {
+    Client::connect(false, move |client| {
+        let Ok(client) = client else {
+            f(Vec::new());
+            return;
+        };
+        client.request("list", ().to_variant(), |_client, answer| match answer {
+            Ok(payload) => f(payload.get::<Vec<u64>>().unwrap_or_default()),
+            Err(e) => {
+                glib::g_warning!(G_LOG_DOMAIN, "can't list the sessions of the daemon: {}", e);
+                f(Vec::new());
+            }
+        });
+    });
+}
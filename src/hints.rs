@@ -0,0 +1,41 @@
+/// keys used to label hints, home row first
+const ALPHABET: &[char] = &[
+    'a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', 'q', 'w', 'e', 'r', 't', 'y', 'u', 'i', 'o', 'p',
+    'z', 'x', 'c', 'v', 'b', 'n', 'm',
+];
+
+/// what to do with the link chosen in hint mode
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HintAction {
+    #[default]
+    Open,
+    Copy,
+}
+
+impl HintAction {
+    pub fn from_name(name: &str) -> Option<HintAction> {
+        match name {
+            "open" => Some(HintAction::Open),
+            "copy" => Some(HintAction::Copy),
+            _ => None,
+        }
+    }
+}
+
+/// build `count` labels of the same length, so that none is a prefix of another
+pub fn labels(count: usize) -> Vec<String> {
+    let mut length = 1;
+    while ALPHABET.len().pow(length) < count {
+        length += 1;
+    }
+    (0..count)
+        .map(|mut i| {
+            let mut label = vec![ALPHABET[0]; length as usize];
+            for c in label.iter_mut().rev() {
+                *c = ALPHABET[i % ALPHABET.len()];
+                i /= ALPHABET.len();
+            }
+            label.into_iter().collect()
+        })
+        .collect()
+}
@@ -1,11 +1,21 @@
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
-use gtk::{gio, glib};
+use gtk::{gdk, gio, glib};
 use gtk4_layer_shell::Edge;
 
+use crate::link::Template;
+use crate::terminal::LayerConsoleTerminal;
+
 pub const DEFAULT_FONT: &str = "Monospace 13";
 pub const DEFAULT_ROWS: i64 = 25;
 pub const DEFAULT_COLUMNS: i64 = 100;
+pub const DEFAULT_NOTIFY_MIN_DURATION: u32 = 10;
+pub const DEFAULT_SCROLLBACK_SAVE_LIMIT: u32 = 1024;
+/// handler of ssh links, running ssh in a new tab with the port of the link if it has one
+pub const DEFAULT_SSH_HANDLER: &str = "tab:port=$3; exec ssh ${port:+-p \"$port\"} $2";
+pub const MIN_FONT_SCALE: f64 = 0.25;
+pub const MAX_FONT_SCALE: f64 = 4.0;
+const FONT_SCALE_STEP: f64 = 1.1;
 
 #[derive(Default, Debug, Eq, PartialEq, Clone, Copy, glib::Enum)]
 #[repr(u32)]
@@ -18,7 +28,41 @@ pub enum Position {
     Right = 3,
 }
 
+/// what to do when the terminal rings the bell
+#[derive(Default, Debug, Eq, PartialEq, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "LayerConsoleBell")]
+pub enum Bell {
+    None = 0,
+    #[default]
+    Audible = 1,
+    /// flash the border of the console
+    Visual = 2,
+    /// mark the console until it is focused, see `set_urgent`
+    Urgent = 3,
+    /// send a desktop notification if the console is not focused
+    Notify = 4,
+}
+
 impl Position {
+    pub const ALL: [Position; 4] = [
+        Position::Top,
+        Position::Bottom,
+        Position::Left,
+        Position::Right,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Position::Top => "top",
+            Position::Bottom => "bottom",
+            Position::Left => "left",
+            Position::Right => "right",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Position> {
+        Position::ALL.into_iter().find(|p| p.name() == name)
+    }
     pub fn to_edge(&self) -> Edge {
         match self {
             Position::Top => Edge::Top,
@@ -30,20 +74,38 @@ impl Position {
 }
 
 mod imp {
-    use super::{Position, DEFAULT_COLUMNS, DEFAULT_FONT, DEFAULT_ROWS};
+    use super::{
+        Bell, Position, DEFAULT_COLUMNS, DEFAULT_FONT, DEFAULT_NOTIFY_MIN_DURATION, DEFAULT_ROWS,
+        DEFAULT_SCROLLBACK_SAVE_LIMIT, DEFAULT_SSH_HANDLER, FONT_SCALE_STEP, MAX_FONT_SCALE,
+        MIN_FONT_SCALE,
+    };
+    use crate::hints::{self, HintAction};
+    use crate::link::{self, Link, Template};
+    use crate::terminal::{LayerConsoleTerminal, MatchKind};
+    use crate::tmux;
+    use crate::util;
     use crate::G_LOG_DOMAIN;
     use const_format::concatcp;
     use gdk::RGBA;
+    use glib::subclass::Signal;
     use glib::GString;
     use gtk::gdk::AppLaunchContext;
     use gtk::gio::{AppInfo, SimpleAction};
     use gtk::subclass::prelude::*;
-    use gtk::{gdk, gio, glib, pango};
+    use gtk::{gdk, gio, glib, graphene, pango};
     use gtk4_layer_shell::{Edge, KeyboardMode, Layer, LayerShell};
-    use std::cell::{Cell, RefCell};
-    use std::collections::HashSet;
+    use std::cell::{Cell, OnceCell, RefCell};
+    use std::collections::HashMap;
+    use std::rc::Rc;
+    use std::sync::OnceLock;
+    use std::time::Duration;
     use vte4::prelude::*;
 
+    /// what tmux prints about a window for `add_tmux_window`
+    const TMUX_WINDOW_FORMAT: &str =
+        "'#{window_id} #{window_active} #{pane_id} #{window_layout} #{window_name}'";
+
+    const PCRE2_CASELESS: u32 = 0x00000008;
     const PCRE2_MULTILINE: u32 = 0x00000400;
 
     // regex for url from kgx
@@ -101,23 +163,234 @@ mod imp {
         "(?:news:|man:|info:)[-[:alnum:]\\Q^_{|}~!\"#$%&'()*+,./;:=?`\\E]+",
     ];
 
+    /// why pasting `text` could run something without the user pressing enter
+    fn unsafe_paste_reason(text: &str) -> Option<&'static str> {
+        if text.contains(['\n', '\r']) {
+            Some("spanning multiple lines")
+        } else if text.chars().any(|c| c.is_control() && c != '\t') {
+            Some("containing control characters")
+        } else {
+            None
+        }
+    }
+
     #[derive(glib::Properties, Default, Debug)]
     #[properties(wrapper_type = super::LayerConsoleWindow)]
     pub struct LayerConsoleWindow {
         stack: gtk::Stack,
-        terminal: vte4::Terminal,
+        /// one terminal per tab, each in a scrolled window
+        notebook: gtk::Notebook,
+        search_bar: gtk::SearchBar,
+        search_entry: gtk::SearchEntry,
+        paste_revealer: gtk::Revealer,
+        paste_label: gtk::Label,
+        paste_cancel_button: gtk::Button,
+        /// tells what is wrong in the config file
+        config_errors_revealer: gtk::Revealer,
+        config_errors_label: gtk::Label,
+        /// text waiting for the user to confirm the paste
+        pending_paste: RefCell<Option<String>>,
+        context_menu: OnceCell<gtk::PopoverMenu>,
+        /// link under the pointer when the context menu was opened
+        context_link: RefCell<Option<Link>>,
+        /// row under the pointer when the context menu was opened
+        context_row: Cell<Option<i64>>,
         #[property(get, set, nullable)]
         working_directory: RefCell<Option<String>>,
+        /// directory reported by the shell through OSC 7
+        #[property(get, nullable)]
+        current_directory: RefCell<Option<String>>,
         #[property(get, set = Self::set_position, builder(Position::Top))]
         position: Cell<Position>,
+        #[property(get, set = Self::set_bell, builder(Bell::Audible))]
+        bell: Cell<Bell>,
+        /// notify when a command finishes or the bell rings while the console is hidden
+        #[property(get, set)]
+        notify_when_hidden: Cell<bool>,
+        /// seconds a command has to run before its completion is notified
+        #[property(get, set)]
+        notify_min_duration: Cell<u32>,
+        notified_while_hidden: Cell<bool>,
+        /// the bell rang while the console was hidden or unfocused
+        #[property(get)]
+        urgent: Cell<bool>,
+        #[property(get, set)]
+        copy_on_select: Cell<bool>,
+        #[property(get, set)]
+        middle_click_paste: Cell<bool>,
+        /// ask before pasting text which would run commands right away
+        #[property(get, set)]
+        confirm_paste: Cell<bool>,
+        /// command run instead of the desktop's default application to open links
+        #[property(get, set, nullable)]
+        opener: RefCell<Option<String>>,
+        /// command opening files found in the terminal, `$1` being the path,
+        /// `$2` the line and `$3` the column
+        #[property(get, set, nullable)]
+        editor: RefCell<Option<String>>,
+        /// hide the console once a link has been opened
+        #[property(get, set)]
+        hide_on_open: Cell<bool>,
+        /// name identifying the console among the others of the application,
+        /// None for the default one
+        #[property(get, set, nullable)]
+        console_name: RefCell<Option<String>>,
+        /// profile of the config file the console runs, None for the default one
+        #[property(get, set, nullable)]
+        profile: RefCell<Option<String>>,
+        /// file opened by the "preferences" action
+        #[property(get, set, nullable)]
+        config_file: RefCell<Option<std::path::PathBuf>>,
 
+        /// keep the scrollback in the session when quitting
+        #[property(get, set)]
+        save_scrollback: Cell<bool>,
+        /// KiB of the end of the scrollback kept in the session
+        #[property(get, set)]
+        scrollback_save_limit: Cell<u32>,
+        /// program started in new tabs, followed by its arguments
+        #[property(get, set)]
+        shell: RefCell<Vec<String>>,
+        /// start the shell as a login shell
+        #[property(get, set)]
+        login_shell: Cell<bool>,
+        /// run the processes of the tabs in the daemon so that they outlive the console
+        #[property(get, set)]
+        daemon: Cell<bool>,
+        /// tmux session whose windows are shown as tabs
+        #[property(get, nullable)]
+        tmux_session: RefCell<Option<String>>,
+        tmux: RefCell<Option<Rc<tmux::Controller>>>,
+        /// terminals showing the tmux panes
+        tmux_panes: RefCell<HashMap<u32, LayerConsoleTerminal>>,
+        tmux_windows: RefCell<HashMap<u32, TmuxWindow>>,
+        font: RefCell<Option<pango::FontDescription>>,
+        font_scale: Cell<f64>,
+        cursor_shape: Cell<Option<vte4::CursorShape>>,
+        cursor_blink_mode: Cell<Option<vte4::CursorBlinkMode>>,
+        /// background and foreground of the cursor
+        cursor_colors: RefCell<(Option<RGBA>, Option<RGBA>)>,
         columns: Cell<i64>,
         rows: Cell<i64>,
         is_fullscreen: Cell<bool>,
-        match_ids: std::cell::RefCell<HashSet<i32>>,
+        link_vte_regexes: RefCell<Vec<vte4::Regex>>,
+        /// same patterns as `link_vte_regexes`, to look for links outside of the pointer
+        link_regexes: RefCell<Vec<glib::Regex>>,
+        matchers: RefCell<Vec<Matcher>>,
+        /// modifiers to hold while clicking a link, control if unset
+        link_modifier: Cell<Option<gdk::ModifierType>>,
+        hint_overlay: gtk::Overlay,
+        hints: RefCell<Vec<Hint>>,
+        hint_input: RefCell<String>,
+        hint_action: Cell<HintAction>,
+        hint_scroll_handler: RefCell<Option<(gtk::Adjustment, glib::SignalHandlerId)>>,
+        file_vte_regex: OnceCell<vte4::Regex>,
+        file_regex: OnceCell<glib::Regex>,
+        /// commands opening the URLs of a scheme, overriding the opener
+        url_handlers: RefCell<HashMap<String, String>>,
+    }
+
+    /// where an activated link was opened
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Opened {
+        /// by another program
+        Outside,
+        /// in a new tab of the console, which is left shown
+        Tab,
+    }
+
+    /// link labeled in hint mode
+    #[derive(Debug)]
+    struct Hint {
+        label: String,
+        text: String,
+        link: Link,
+        widget: gtk::Label,
+    }
+
+    /// tab showing a tmux window
+    #[derive(Debug)]
+    struct TmuxWindow {
+        page: gtk::Box,
+        /// panes of the window, from the top left
+        panes: Vec<u32>,
+        active_pane: Option<u32>,
+    }
+
+    /// user defined matcher, registered to the terminals by its index
+    #[derive(Debug)]
+    struct Matcher {
+        vte_regex: vte4::Regex,
+        /// same pattern as given to the terminals, used to extract the groups
+        regex: glib::Regex,
+        template: Template,
+    }
+
+    impl Matcher {
+        fn expand(&self, match_info: &glib::MatchInfo) -> Link {
+            let captures = (0..=self.regex.capture_count())
+                .map(|i| match_info.fetch(i))
+                .collect::<Vec<_>>();
+            self.template.expand(&captures)
+        }
+    }
+
+    /// call `f` for every match of `regex` in `text`
+    fn for_each_match(regex: &glib::Regex, text: &glib::GStr, mut f: impl FnMut(&glib::MatchInfo)) {
+        let Some(match_info) = regex.match_(text, glib::RegexMatchFlags::DEFAULT) else {
+            return;
+        };
+        while match_info.matches() {
+            f(&match_info);
+            if !matches!(match_info.next(), Ok(true)) {
+                break;
+            }
+        }
+    }
+
+    fn page_terminal(page: &gtk::Widget) -> Option<LayerConsoleTerminal> {
+        page.downcast_ref::<gtk::ScrolledWindow>()?
+            .child()?
+            .downcast()
+            .ok()
+    }
+
+    /// take `widget` out of the box or paned holding it
+    fn detach_widget(widget: &gtk::Widget) {
+        let Some(parent) = widget.parent() else {
+            return;
+        };
+        if let Some(paned) = parent.downcast_ref::<gtk::Paned>() {
+            if paned.start_child().as_ref() == Some(widget) {
+                paned.set_start_child(None::<&gtk::Widget>);
+            } else {
+                paned.set_end_child(None::<&gtk::Widget>);
+            }
+        } else if let Some(container) = parent.downcast_ref::<gtk::Box>() {
+            container.remove(widget);
+        }
     }
 
     impl LayerConsoleWindow {
+        /// terminal of the current tab, the active pane for a tmux window
+        fn terminal(&self) -> Option<LayerConsoleTerminal> {
+            let page = self.notebook.nth_page(self.notebook.current_page())?;
+            match self.tmux_window_at(&page) {
+                Some(window) => {
+                    let pane = self.tmux_windows.borrow().get(&window)?.active_pane?;
+                    self.tmux_panes.borrow().get(&pane).cloned()
+                }
+                None => page_terminal(&page),
+            }
+        }
+        /// terminals of the tabs followed by the ones of the tmux panes
+        pub fn terminals(&self) -> Vec<LayerConsoleTerminal> {
+            (0..self.notebook.n_pages())
+                .filter_map(|i| self.notebook.nth_page(Some(i)))
+                .filter_map(|page| page_terminal(&page))
+                .chain(self.tmux_panes.borrow().values().cloned())
+                .collect()
+        }
         fn set_position(&self, position: Position) {
             if self.position.get() == position {
                 return;
@@ -125,32 +398,239 @@ mod imp {
             self.position.replace(position);
             self.set_anchors();
             self.set_css_class();
+            if let Some(action) = self.obj().lookup_action("move-to") {
+                action
+                    .downcast::<SimpleAction>()
+                    .unwrap()
+                    .set_state(&position.name().to_variant());
+            }
+            self.state_changed();
+        }
+        /// tell that something saved in the session changed
+        fn state_changed(&self) {
+            self.obj().emit_by_name::<()>("state-changed", &[]);
+        }
+        fn set_bell(&self, bell: Bell) {
+            self.bell.replace(bell);
+            for terminal in self.terminals() {
+                terminal.set_audible_bell(bell == Bell::Audible);
+            }
+        }
+        fn ring_bell(&self, terminal: &LayerConsoleTerminal) {
+            let window = self.obj();
+            match self.bell.get() {
+                Bell::None | Bell::Audible => (),
+                Bell::Visual => self.flash(terminal),
+                Bell::Urgent => {
+                    if window.is_visible() && window.is_active() {
+                        self.flash(terminal);
+                    } else {
+                        self.set_urgent(true);
+                    }
+                }
+                Bell::Notify => {
+                    if window.is_visible() && window.is_active() {
+                        return;
+                    }
+                    self.notify_bell(terminal);
+                    return;
+                }
+            }
+            if self.notify_when_hidden.get() && !window.is_visible() {
+                self.notify_bell(terminal);
+            }
+        }
+        fn notify_bell(&self, terminal: &LayerConsoleTerminal) {
+            let body = terminal
+                .title()
+                .or_else(|| self.current_directory.borrow().clone());
+            self.send_notification("bell", "Bell in layer-console", body.as_deref());
+        }
+        /// send a desktop notification showing the console when clicked
+        fn send_notification(&self, id: &str, title: &str, body: Option<&str>) {
+            let Some(app) = self.obj().application() else {
+                return;
+            };
+            let notification = gio::Notification::new(title);
+            notification.set_body(body);
+            let name = self.console_name.borrow().clone().unwrap_or_default();
+            notification
+                .set_default_action_and_target_value("app.show-console", Some(&name.to_variant()));
+            // one notification of each kind per console
+            app.send_notification(Some(&format!("{}:{}", id, name)), &notification);
+        }
+        /// guess that a command finished from the shell updating the title,
+        /// as most of them show the running command there and reset it on the
+        /// prompt. its exit status is only known with the shell integration
+        fn on_window_title_changed(&self, terminal: &LayerConsoleTerminal) {
+            self.state_changed();
+            // tmux names its windows itself
+            if let Some(page) = terminal.parent().filter(|_| !terminal.has_input_handler()) {
+                self.notebook.set_tab_label_text(&page, &terminal.label());
+            }
+            let Some((previous_title, elapsed)) = terminal.track_title() else {
+                return;
+            };
+            // the prompt marks tell for sure, see on_command_finished
+            if terminal.has_shell_integration()
+                || !self.notify_when_hidden.get()
+                || self.obj().is_visible()
+                || self.notified_while_hidden.get()
+            {
+                return;
+            }
+            if elapsed < Duration::from_secs(self.notify_min_duration.get().into()) {
+                return;
+            }
+            self.notified_while_hidden.set(true);
+            let body = format!(
+                "{} (after {}, exit status unknown without the shell integration)",
+                previous_title.as_deref().unwrap_or("command"),
+                util::format_duration(elapsed)
+            );
+            self.send_notification("finished", "Command finished", Some(&body));
+        }
+        fn on_command_finished(&self, terminal: &LayerConsoleTerminal) {
+            let Some(command) = terminal.last_command() else {
+                return;
+            };
+            let duration = command.duration.unwrap_or_default();
+            if !self.notify_when_hidden.get()
+                || self.obj().is_visible()
+                || self.notified_while_hidden.get()
+                || duration < Duration::from_secs(self.notify_min_duration.get().into())
+            {
+                return;
+            }
+            self.notified_while_hidden.set(true);
+            let title = match command.status {
+                Some(0) | None => "Command finished".to_string(),
+                Some(status) => format!("Command failed with status {}", status),
+            };
+            let body = format!(
+                "{} (after {})",
+                command.command.as_deref().unwrap_or("command"),
+                util::format_duration(duration)
+            );
+            self.send_notification("finished", &title, Some(&body));
+        }
+        fn flash(&self, terminal: &LayerConsoleTerminal) {
+            terminal.add_css_class("bell");
+            glib::timeout_add_local_once(
+                std::time::Duration::from_millis(150),
+                glib::clone!(
+                    #[weak]
+                    terminal,
+                    move || terminal.remove_css_class("bell")
+                ),
+            );
+        }
+        pub fn set_cursor_shape(&self, shape: vte4::CursorShape) {
+            self.cursor_shape.set(Some(shape));
+            for terminal in self.terminals() {
+                terminal.set_cursor_shape(shape);
+            }
+        }
+        pub fn set_cursor_blink_mode(&self, mode: vte4::CursorBlinkMode) {
+            self.cursor_blink_mode.set(Some(mode));
+            for terminal in self.terminals() {
+                terminal.set_cursor_blink_mode(mode);
+            }
+        }
+        pub fn set_cursor_colors(&self, background: Option<&RGBA>, foreground: Option<&RGBA>) {
+            self.cursor_colors
+                .replace((background.copied(), foreground.copied()));
+            for terminal in self.terminals() {
+                terminal.set_color_cursor(background);
+                terminal.set_color_cursor_foreground(foreground);
+            }
         }
         pub fn set_font(&self, font: &str) {
-            self.terminal
-                .set_font(Some(&pango::FontDescription::from_string(font)));
+            let font = pango::FontDescription::from_string(font);
+            for terminal in self.terminals() {
+                terminal.set_font(Some(&font));
+            }
+            self.font.replace(Some(font));
         }
         fn set_css_class(&self) {
-            let class_name = match self.position.get() {
-                Position::Top => "top",
-                Position::Bottom => "bottom",
-                Position::Left => "left",
-                Position::Right => "right",
-            };
-            self.terminal.set_css_classes(&[class_name]);
+            for terminal in self.terminals() {
+                for position in Position::ALL {
+                    terminal.remove_css_class(position.name());
+                }
+                terminal.add_css_class(self.position.get().name());
+            }
         }
+        /// mark the console as waiting for the user until it is focused,
+        /// shown by its border and in `--status`
+        fn set_urgent(&self, urgent: bool) {
+            if self.urgent.replace(urgent) == urgent {
+                return;
+            }
+            let window = self.obj();
+            if urgent {
+                window.add_css_class("urgent");
+            } else {
+                window.remove_css_class("urgent");
+            }
+            window.notify_urgent();
+        }
+        /// set the size of the console, in cells of the unscaled font
         pub fn set_terminal_size(&self, columns: Option<i64>, rows: Option<i64>) {
-            let columns = columns.unwrap_or_else(|| self.terminal.column_count());
-            let rows = rows.unwrap_or_else(|| self.terminal.row_count());
-            self.columns.replace(columns);
-            self.rows.replace(rows);
-            self.terminal.set_size(columns, rows);
+            if let Some(columns) = columns {
+                self.columns.replace(columns);
+            }
+            if let Some(rows) = rows {
+                self.rows.replace(rows);
+            }
+            self.state_changed();
+            if !self.is_fullscreen.get() {
+                self.apply_terminal_size();
+            }
+        }
+        pub fn terminal_size(&self) -> (i64, i64) {
+            (self.columns.get(), self.rows.get())
+        }
+        /// size of the terminals covering the same area of the screen
+        /// whatever the current font scale is
+        fn scaled_terminal_size(&self) -> (i64, i64) {
+            let scale = self.font_scale.get();
+            let columns = (self.columns.get() as f64 / scale).round() as i64;
+            let rows = (self.rows.get() as f64 / scale).round() as i64;
+            (columns.max(1), rows.max(1))
+        }
+        fn apply_terminal_size(&self) {
+            let (columns, rows) = self.scaled_terminal_size();
+            // tmux sizes its panes
+            for terminal in self.terminals() {
+                if !terminal.has_input_handler() {
+                    terminal.set_size(columns, rows);
+                }
+            }
+            if let Some(controller) = self.tmux.borrow().as_ref() {
+                controller.set_size(columns, rows);
+            }
+        }
+        pub fn set_font_scale(&self, scale: f64) {
+            let scale = scale.clamp(MIN_FONT_SCALE, MAX_FONT_SCALE);
+            self.font_scale.set(scale);
+            for terminal in self.terminals() {
+                terminal.set_font_scale(scale);
+            }
+            if !self.is_fullscreen.get() {
+                self.apply_terminal_size();
+            }
+        }
+        pub fn zoom_in(&self) {
+            self.set_font_scale(self.font_scale.get() * FONT_SCALE_STEP);
+        }
+        pub fn zoom_out(&self) {
+            self.set_font_scale(self.font_scale.get() / FONT_SCALE_STEP);
         }
         pub fn fullscreen(&self) {
             if self.is_fullscreen.get() {
                 self.set_anchors();
-                self.terminal.set_size(self.columns.get(), self.rows.get());
                 self.is_fullscreen.replace(false);
+                self.apply_terminal_size();
             } else {
                 let window = self.obj();
                 for edge in [Edge::Top, Edge::Bottom, Edge::Left, Edge::Right] {
@@ -159,46 +639,1439 @@ mod imp {
                 self.is_fullscreen.replace(true);
             }
         }
-        pub fn spawn(&self, args: &[&str]) {
-            self.terminal.spawn_async(
-                vte4::PtyFlags::DEFAULT,
-                self.working_directory.borrow().as_deref(),
-                args,
+        /// directory new processes should start in: the shell's current
+        /// directory if it reported one, the configured one otherwise
+        fn spawn_directory(&self) -> Option<String> {
+            self.current_directory
+                .borrow()
+                .clone()
+                .or_else(|| self.working_directory.borrow().clone())
+        }
+        /// open a tab running `args` in `directory`, the one of the current
+        /// tab if unset, `title` being the label of the tab instead of the
+        /// title of the terminal. `login_shell` runs it as a login shell.
+        pub fn add_tab(
+            &self,
+            args: &[&str],
+            directory: Option<&str>,
+            title: Option<&str>,
+            env: &[&str],
+            login_shell: bool,
+        ) -> LayerConsoleTerminal {
+            let directory = directory
+                .map(str::to_string)
+                .or_else(|| self.spawn_directory());
+            let terminal = self.new_page(title);
+            if self.daemon.get() {
+                terminal.spawn_in_daemon(directory.as_deref(), args, env, login_shell);
+            } else {
+                terminal.spawn(directory.as_deref(), args, env, login_shell);
+            }
+            self.tab_started(&terminal);
+            terminal
+        }
+        /// open a tab following the process left in the daemon by a previous
+        /// console, `on_error` being called if it is gone
+        pub fn attach_tab<F: FnOnce(&LayerConsoleTerminal, String) + 'static>(
+            &self,
+            id: u64,
+            title: Option<&str>,
+            on_error: F,
+        ) -> LayerConsoleTerminal {
+            let terminal = self.new_page(title);
+            terminal.attach(id, on_error);
+            self.tab_started(&terminal);
+            terminal
+        }
+        /// add a tab with a terminal nothing runs in yet
+        fn new_page(&self, title: Option<&str>) -> LayerConsoleTerminal {
+            let terminal = LayerConsoleTerminal::default();
+            terminal.set_tab_title(title.map(str::to_string));
+            self.setup_terminal(&terminal);
+            let scrolled = gtk::ScrolledWindow::builder()
+                .vexpand(true)
+                .propagate_natural_height(true)
+                .propagate_natural_width(true)
+                .hscrollbar_policy(gtk::PolicyType::Never)
+                .child(&terminal)
+                .build();
+            self.notebook.append_page(&scrolled, None::<&gtk::Widget>);
+            self.notebook.set_tab_reorderable(&scrolled, true);
+            terminal
+        }
+        fn tab_started(&self, terminal: &LayerConsoleTerminal) {
+            let Some(page) = terminal.parent() else {
+                return;
+            };
+            self.notebook.set_tab_label_text(&page, &terminal.label());
+            self.notebook
+                .set_current_page(self.notebook.page_num(&page));
+            self.state_changed();
+        }
+        /// open a tab running the shell, or a new window of the tmux session
+        fn new_tab(&self) {
+            if let Some(controller) = self.tmux.borrow().as_ref() {
+                controller.run("new-window");
+                return;
+            }
+            let shell = self.shell_command();
+            self.add_tab(
+                &shell.iter().map(String::as_str).collect::<Vec<_>>(),
+                None,
+                None,
                 &[],
-                glib::SpawnFlags::DEFAULT,
-                || {},
-                -1,
+                self.login_shell.get(),
+            );
+        }
+        /// open a tab running `args`, or a new window of the tmux session
+        fn run_in_new_tab(&self, args: &[&str]) {
+            if let Some(controller) = self.tmux.borrow().as_ref() {
+                let command = args
+                    .iter()
+                    .map(|arg| glib::shell_quote(arg).to_string_lossy().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                controller.run(&format!("new-window {}", command));
+                return;
+            }
+            self.add_tab(args, None, None, &[], false);
+        }
+        /// the command line of the shell, run as a login shell with `login_shell`
+        pub fn shell_command(&self) -> Vec<String> {
+            let mut command = self.shell.borrow().clone();
+            if command.is_empty() {
+                command.push(util::get_user_shell());
+            }
+            command
+        }
+        pub fn close_tab(&self, terminal: &LayerConsoleTerminal) {
+            let Some(page) = terminal.parent() else {
+                return;
+            };
+            if let Some(index) = self.notebook.page_num(&page) {
+                self.notebook.remove_page(Some(index));
+            }
+            if self.notebook.n_pages() == 0 {
+                self.obj().close();
+            } else {
+                self.state_changed();
+            }
+        }
+        pub fn current_tab(&self) -> u32 {
+            self.notebook.current_page().unwrap_or(0)
+        }
+        pub fn set_current_tab(&self, index: u32) {
+            if index < self.notebook.n_pages() {
+                self.notebook.set_current_page(Some(index));
+            }
+        }
+        /// show the windows of the tmux `session` as tabs and their panes as
+        /// splits, the session being created if needed
+        pub fn attach_tmux(&self, session: &str) -> Result<(), String> {
+            let controller = tmux::Controller::start(
+                session,
+                glib::clone!(
+                    #[weak(rename_to = this)]
+                    self,
+                    move |event| this.on_tmux_event(event)
+                ),
+            )?;
+            self.tmux_session.replace(Some(session.to_string()));
+            self.tmux.replace(Some(controller));
+            self.apply_terminal_size();
+            self.query_tmux_windows(&format!("list-windows -F {}", TMUX_WINDOW_FORMAT));
+            self.state_changed();
+            Ok(())
+        }
+        fn on_tmux_event(&self, event: tmux::Event) {
+            match event {
+                tmux::Event::Output { pane, data } => {
+                    let terminal = self.tmux_panes.borrow().get(&pane).cloned();
+                    if let Some(terminal) = terminal {
+                        terminal.feed_output(&data);
+                    }
+                }
+                tmux::Event::WindowAdd(window) => {
+                    self.query_tmux_windows(&format!(
+                        "display-message -p -t @{} {}",
+                        window, TMUX_WINDOW_FORMAT
+                    ));
+                }
+                tmux::Event::WindowClose(window) => self.remove_tmux_window(window),
+                tmux::Event::WindowRenamed { window, name } => {
+                    if let Some(page) = self.tmux_page(window) {
+                        self.notebook.set_tab_label_text(&page, &name);
+                    }
+                }
+                tmux::Event::LayoutChange { window, layout } => {
+                    self.update_tmux_layout(window, &layout);
+                }
+                tmux::Event::WindowPaneChanged { window, pane } => {
+                    if let Some(tmux_window) = self.tmux_windows.borrow_mut().get_mut(&window) {
+                        tmux_window.active_pane = Some(pane);
+                    }
+                    if self.tmux_page(window).is_some_and(|page| {
+                        self.notebook.page_num(&page) == self.notebook.current_page()
+                    }) {
+                        self.on_tab_switched();
+                    }
+                }
+                tmux::Event::SessionWindowChanged(window) => {
+                    if let Some(page) = self.tmux_page(window) {
+                        self.notebook
+                            .set_current_page(self.notebook.page_num(&page));
+                    }
+                }
+                tmux::Event::Exit(reason) => self.on_tmux_exit(reason),
+            }
+        }
+        /// run `command`, printing windows in TMUX_WINDOW_FORMAT, and add their tabs
+        fn query_tmux_windows(&self, command: &str) {
+            let Some(controller) = self.tmux.borrow().clone() else {
+                return;
+            };
+            controller.command(
+                command,
+                glib::clone!(
+                    #[weak(rename_to = this)]
+                    self,
+                    move |result| match result {
+                        Ok(lines) => {
+                            for line in lines {
+                                this.add_tmux_window(&line);
+                            }
+                        }
+                        Err(e) => {
+                            glib::g_warning!(G_LOG_DOMAIN, "can't list the tmux windows: {}", e);
+                        }
+                    }
+                ),
+            );
+        }
+        /// add the tab of a window described with TMUX_WINDOW_FORMAT, or update it
+        fn add_tmux_window(&self, description: &str) {
+            let mut fields = description.splitn(5, ' ');
+            let id = fields.next().and_then(|id| tmux::parse_id(id, '@'));
+            let active = fields.next() == Some("1");
+            let pane = fields.next().and_then(|pane| tmux::parse_id(pane, '%'));
+            let (Some(id), Some(layout)) = (id, fields.next()) else {
+                glib::g_warning!(G_LOG_DOMAIN, "unexpected tmux window `{}`", description);
+                return;
+            };
+            let name = fields.next().unwrap_or_default();
+            let page = self.tmux_page(id).unwrap_or_else(|| {
+                let page = gtk::Box::new(gtk::Orientation::Vertical, 0);
+                self.tmux_windows.borrow_mut().insert(
+                    id,
+                    TmuxWindow {
+                        page: page.clone(),
+                        panes: Vec::new(),
+                        active_pane: None,
+                    },
+                );
+                self.notebook.append_page(&page, None::<&gtk::Widget>);
+                self.notebook.set_tab_reorderable(&page, true);
+                page
+            });
+            if let Some(tmux_window) = self.tmux_windows.borrow_mut().get_mut(&id) {
+                tmux_window.active_pane = pane;
+            }
+            self.notebook.set_tab_label_text(&page, name);
+            self.update_tmux_layout(id, layout);
+            if active {
+                self.notebook
+                    .set_current_page(self.notebook.page_num(&page));
+            }
+        }
+        fn tmux_page(&self, window: u32) -> Option<gtk::Box> {
+            self.tmux_windows
+                .borrow()
+                .get(&window)
+                .map(|tmux_window| tmux_window.page.clone())
+        }
+        /// the tmux window shown by the tab `page`
+        fn tmux_window_at(&self, page: &gtk::Widget) -> Option<u32> {
+            self.tmux_windows
+                .borrow()
+                .iter()
+                .find(|(_, tmux_window)| tmux_window.page.upcast_ref::<gtk::Widget>() == page)
+                .map(|(id, _)| *id)
+        }
+        /// the active pane of the current tab if it shows a tmux window
+        fn current_tmux_pane(&self) -> Option<u32> {
+            let page = self.notebook.nth_page(self.notebook.current_page())?;
+            let window = self.tmux_window_at(&page)?;
+            self.tmux_windows.borrow().get(&window)?.active_pane
+        }
+        /// arrange the panes of `window` following its tmux layout
+        fn update_tmux_layout(&self, window: u32, layout: &str) {
+            let Some(layout) = tmux::Layout::parse(layout) else {
+                glib::g_warning!(G_LOG_DOMAIN, "invalid tmux layout `{}`", layout);
+                return;
+            };
+            let Some(page) = self.tmux_page(window) else {
+                return;
+            };
+            let panes = layout.panes();
+            let previous_panes = self
+                .tmux_windows
+                .borrow_mut()
+                .get_mut(&window)
+                .map(|tmux_window| std::mem::replace(&mut tmux_window.panes, panes.clone()))
+                .unwrap_or_default();
+            // the terminals move to the new splits, the ones of closed panes go away
+            let mut focused = None;
+            for pane in previous_panes {
+                let terminal = if panes.contains(&pane) {
+                    self.tmux_panes.borrow().get(&pane).cloned()
+                } else {
+                    self.tmux_panes.borrow_mut().remove(&pane)
+                };
+                if let Some(terminal) = terminal {
+                    if terminal.has_focus() {
+                        focused = Some(terminal.clone());
+                    }
+                    detach_widget(terminal.upcast_ref());
+                }
+            }
+            while let Some(child) = page.first_child() {
+                page.remove(&child);
+            }
+            page.append(&self.build_tmux_layout(&layout));
+            if let Some(terminal) = focused.filter(|terminal| terminal.parent().is_some()) {
+                terminal.grab_focus();
+            }
+        }
+        fn build_tmux_layout(&self, layout: &tmux::Layout) -> gtk::Widget {
+            match layout {
+                tmux::Layout::Pane { id, columns, rows } => {
+                    let terminal = self.tmux_pane(*id);
+                    terminal.set_size(*columns, *rows);
+                    terminal.upcast()
+                }
+                tmux::Layout::Split {
+                    horizontal,
+                    children,
+                } => {
+                    let orientation = if *horizontal {
+                        gtk::Orientation::Horizontal
+                    } else {
+                        gtk::Orientation::Vertical
+                    };
+                    // a paned holds two children, the next ones go in nested paneds
+                    children
+                        .iter()
+                        .map(|child| self.build_tmux_layout(child))
+                        .rev()
+                        .reduce(|end, start| {
+                            gtk::Paned::builder()
+                                .orientation(orientation)
+                                .start_child(&start)
+                                .end_child(&end)
+                                .shrink_start_child(false)
+                                .shrink_end_child(false)
+                                .build()
+                                .upcast()
+                        })
+                        .unwrap_or_else(|| gtk::Box::new(orientation, 0).upcast())
+                }
+            }
+        }
+        /// the terminal of the tmux pane `id`, created on first use
+        fn tmux_pane(&self, id: u32) -> LayerConsoleTerminal {
+            if let Some(terminal) = self.tmux_panes.borrow().get(&id) {
+                return terminal.clone();
+            }
+            let terminal = LayerConsoleTerminal::default();
+            self.setup_terminal(&terminal);
+            // tmux keeps the history, scrolled in its copy mode
+            terminal.set_scrollback_lines(0);
+            terminal.set_hexpand(true);
+            terminal.set_vexpand(true);
+            let controller = self
+                .tmux
+                .borrow()
+                .as_ref()
+                .map(Rc::downgrade)
+                .unwrap_or_default();
+            let focus = gtk::EventControllerFocus::new();
+            focus.connect_enter(glib::clone!(
+                #[strong]
+                controller,
+                move |_| {
+                    if let Some(controller) = controller.upgrade() {
+                        controller.run(&format!("select-pane -t %{}", id));
+                    }
+                }
+            ));
+            terminal.add_controller(focus);
+            terminal.set_input_handler(move |data| {
+                if let Some(controller) = controller.upgrade() {
+                    controller.send_keys(id, data);
+                }
+            });
+            self.tmux_panes.borrow_mut().insert(id, terminal.clone());
+            self.capture_tmux_pane(id, &terminal);
+            terminal
+        }
+        /// show what the pane already shows, the next output following from there
+        fn capture_tmux_pane(&self, id: u32, terminal: &LayerConsoleTerminal) {
+            let Some(controller) = self.tmux.borrow().clone() else {
+                return;
+            };
+            controller.command(
+                &format!("capture-pane -p -e -t %{}", id),
+                glib::clone!(
+                    #[weak]
+                    terminal,
+                    move |result| match result {
+                        Ok(lines) => {
+                            terminal.feed_output(b"\x1b[H\x1b[2J");
+                            terminal.feed_output(lines.join("\r\n").as_bytes());
+                        }
+                        Err(e) => {
+                            glib::g_warning!(G_LOG_DOMAIN, "can't capture tmux pane {}: {}", id, e);
+                        }
+                    }
+                ),
+            );
+            controller.command(
+                &format!(
+                    "display-message -p -t %{} '#{{cursor_x}} #{{cursor_y}}'",
+                    id
+                ),
+                glib::clone!(
+                    #[weak]
+                    terminal,
+                    move |result| {
+                        let cursor = result.ok().and_then(|lines| {
+                            let (x, y) = lines.first()?.split_once(' ')?;
+                            Some((x.parse::<u32>().ok()?, y.parse::<u32>().ok()?))
+                        });
+                        if let Some((x, y)) = cursor {
+                            terminal.feed_output(format!("\x1b[{};{}H", y + 1, x + 1).as_bytes());
+                        }
+                    }
+                ),
+            );
+        }
+        fn remove_tmux_window(&self, window: u32) {
+            let Some(tmux_window) = self.tmux_windows.borrow_mut().remove(&window) else {
+                return;
+            };
+            for pane in &tmux_window.panes {
+                self.tmux_panes.borrow_mut().remove(pane);
+            }
+            if let Some(index) = self.notebook.page_num(&tmux_window.page) {
+                self.notebook.remove_page(Some(index));
+            }
+        }
+        /// drop the tabs of tmux once it is gone, closing the console if nothing is left
+        fn on_tmux_exit(&self, reason: Option<String>) {
+            if let Some(reason) = reason {
+                glib::g_warning!(G_LOG_DOMAIN, "tmux exited: {}", reason);
+            }
+            // the end of its output follows the %exit notification
+            if self.tmux.take().is_none() {
+                return;
+            }
+            self.tmux_session.take();
+            let windows = self
+                .tmux_windows
+                .borrow()
+                .keys()
+                .copied()
+                .collect::<Vec<_>>();
+            for window in windows {
+                self.remove_tmux_window(window);
+            }
+            if self.notebook.n_pages() == 0 {
+                self.obj().close();
+            } else {
+                self.state_changed();
+            }
+        }
+        /// give a new terminal the settings of the console and follow its signals
+        fn setup_terminal(&self, terminal: &LayerConsoleTerminal) {
+            self.set_terminal_colors(terminal);
+            terminal.set_bold_is_bright(true);
+            terminal.set_allow_hyperlink(true);
+            terminal.set_audible_bell(self.bell.get() == Bell::Audible);
+            terminal.set_font(self.font.borrow().as_ref());
+            terminal.set_font_scale(self.font_scale.get());
+            if let Some(shape) = self.cursor_shape.get() {
+                terminal.set_cursor_shape(shape);
+            }
+            if let Some(mode) = self.cursor_blink_mode.get() {
+                terminal.set_cursor_blink_mode(mode);
+            }
+            let (background, foreground) = *self.cursor_colors.borrow();
+            terminal.set_color_cursor(background.as_ref());
+            terminal.set_color_cursor_foreground(foreground.as_ref());
+            terminal.add_css_class(self.position.get().name());
+            terminal.search_set_wrap_around(true);
+            self.register_matches(terminal);
+            let (columns, rows) = self.scaled_terminal_size();
+            terminal.set_size(columns, rows);
+
+            terminal.connect_child_exited(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |terminal, _status| this.close_tab(terminal)
+            ));
+            terminal.connect_window_title_changed(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |terminal| this.on_window_title_changed(terminal)
+            ));
+            terminal.connect_command_finished(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |terminal| this.on_command_finished(terminal)
+            ));
+            terminal.connect_bell(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |terminal| this.ring_bell(terminal)
+            ));
+            terminal.connect_current_directory_uri_changed(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |terminal| {
+                    if this.terminal().as_ref() == Some(terminal) {
+                        this.update_current_directory();
+                    }
+                }
+            ));
+            terminal.connect_selection_changed(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |terminal| {
+                    if this.copy_on_select.get() && terminal.has_selection() {
+                        terminal.copy_clipboard_format(vte4::Format::Text);
+                    }
+                }
+            ));
+            terminal.connect_hyperlink_hover_uri_notify(|terminal| {
+                terminal.set_tooltip_text(terminal.hyperlink_hover_uri().as_deref());
+            });
+
+            let gesture = gtk::GestureClick::new();
+            gesture.set_button(1);
+            gesture.connect_pressed(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                #[weak]
+                terminal,
+                move |gesture, n, x, y| {
+                    if n > 1 {
+                        gesture.set_state(gtk::EventSequenceState::Denied);
+                        return;
+                    }
+                    let modifiers = gesture.current_event_state()
+                        & (gdk::ModifierType::CONTROL_MASK
+                            | gdk::ModifierType::SHIFT_MASK
+                            | gdk::ModifierType::ALT_MASK);
+                    let link_modifier = this
+                        .link_modifier
+                        .get()
+                        .unwrap_or(gdk::ModifierType::CONTROL_MASK);
+                    if modifiers != link_modifier {
+                        gesture.set_state(gtk::EventSequenceState::Denied);
+                        return;
+                    }
+                    if let Some(link) = this.get_link(&terminal, x, y) {
+                        this.open_link(&link);
+                        gesture.set_state(gtk::EventSequenceState::Claimed);
+                        return;
+                    }
+                    gesture.set_state(gtk::EventSequenceState::Denied);
+                }
+            ));
+            terminal.add_controller(gesture);
+
+            // take over middle click so that pasting the selection goes through
+            // the same checks as the clipboard. programs tracking the mouse get
+            // it, unless shift is held as VTE does
+            let gesture = gtk::GestureClick::new();
+            gesture.set_button(2);
+            gesture.set_propagation_phase(gtk::PropagationPhase::Capture);
+            gesture.connect_pressed(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                #[weak]
+                terminal,
+                move |gesture, _n, _x, _y| {
+                    let shift = gesture
+                        .current_event_state()
+                        .contains(gdk::ModifierType::SHIFT_MASK);
+                    if terminal.tracks_mouse() && !shift {
+                        gesture.set_state(gtk::EventSequenceState::Denied);
+                        return;
+                    }
+                    if !this.middle_click_paste.get() {
+                        gesture.set_state(gtk::EventSequenceState::Denied);
+                        return;
+                    }
+                    this.paste_selection();
+                    gesture.set_state(gtk::EventSequenceState::Claimed);
+                }
+            ));
+            terminal.add_controller(gesture);
+
+            let gesture = gtk::GestureClick::new();
+            gesture.set_button(3);
+            gesture.connect_pressed(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                #[weak]
+                terminal,
+                move |gesture, _n, x, y| {
+                    this.popup_context_menu(&terminal, x, y);
+                    gesture.set_state(gtk::EventSequenceState::Claimed);
+                }
+            ));
+            terminal.add_controller(gesture);
+        }
+        fn on_tab_switched(&self) {
+            let window = self
+                .notebook
+                .nth_page(self.notebook.current_page())
+                .and_then(|page| self.tmux_window_at(&page));
+            if let (Some(window), Some(controller)) = (window, self.tmux.borrow().as_ref()) {
+                controller.run(&format!("select-window -t @{}", window));
+            }
+            self.cancel_hints();
+            self.update_current_directory();
+            if self.search_bar.is_search_mode() {
+                self.update_search();
+            }
+            if let Some(terminal) = self.terminal() {
+                terminal.grab_focus();
+            }
+            self.state_changed();
+        }
+        pub fn add_matcher(&self, pattern: &str, template: Template) -> Result<(), glib::Error> {
+            let vte_regex = vte4::Regex::for_match(pattern, PCRE2_MULTILINE)?;
+            let Some(regex) = glib::Regex::new(
+                pattern,
+                glib::RegexCompileFlags::MULTILINE,
+                glib::RegexMatchFlags::DEFAULT,
+            )?
+            else {
+                return Ok(());
+            };
+            self.matchers.borrow_mut().push(Matcher {
+                vte_regex,
+                regex,
+                template,
+            });
+            for terminal in self.terminals() {
+                self.register_matches(&terminal);
+            }
+            Ok(())
+        }
+        pub fn clear_matchers(&self) {
+            self.matchers.borrow_mut().clear();
+            for terminal in self.terminals() {
+                self.register_matches(&terminal);
+            }
+        }
+        /// give the terminal every regex to detect, user defined matchers
+        /// taking precedence over file paths
+        fn register_matches(&self, terminal: &LayerConsoleTerminal) {
+            terminal.clear_matches();
+            for regex in self.link_vte_regexes.borrow().iter() {
+                terminal.add_match(regex, MatchKind::Url);
+            }
+            for (i, matcher) in self.matchers.borrow().iter().enumerate() {
+                terminal.add_match(&matcher.vte_regex, MatchKind::Matcher(i));
+            }
+            if let Some(regex) = self.file_vte_regex.get() {
+                terminal.add_match(regex, MatchKind::File);
+            }
+        }
+        fn get_link(&self, terminal: &LayerConsoleTerminal, x: f64, y: f64) -> Option<Link> {
+            if let Some(hyperlink) = terminal.check_hyperlink_at(x, y) {
+                return Some(Link::Url(hyperlink.to_string()));
+            }
+            let (text, kind) = terminal.match_at(x, y)?;
+            let i = match kind {
+                MatchKind::Url => return Some(Link::Url(link::normalize_url(&text))),
+                MatchKind::File => return self.file_link(text.as_gstr()),
+                MatchKind::Matcher(i) => i,
+            };
+            let matchers = self.matchers.borrow();
+            let matcher = matchers.get(i)?;
+            let match_info = matcher
+                .regex
+                .match_(text.as_gstr(), glib::RegexMatchFlags::DEFAULT)?;
+            Some(matcher.expand(&match_info))
+        }
+        fn file_link(&self, text: &glib::GStr) -> Option<Link> {
+            let match_info = self
+                .file_regex
+                .get()?
+                .match_(text, glib::RegexMatchFlags::DEFAULT)?;
+            self.file_link_from_match(&match_info)
+        }
+        fn file_link_from_match(&self, match_info: &glib::MatchInfo) -> Option<Link> {
+            let directory = self.spawn_directory().map(std::path::PathBuf::from);
+            link::resolve_file(
+                &match_info.fetch(1)?,
+                match_info.fetch(2).as_deref().filter(|s| !s.is_empty()),
+                match_info.fetch(3).as_deref().filter(|s| !s.is_empty()),
+                directory.as_deref(),
+            )
+        }
+        fn setup_file_matches(&self) {
+            match vte4::Regex::for_match(link::FILE_PATTERN, PCRE2_MULTILINE) {
+                Err(e) => {
+                    glib::g_warning!(G_LOG_DOMAIN, "file regex failed: {}", e);
+                    return;
+                }
+                Ok(regex) => {
+                    self.file_vte_regex.set(regex).unwrap();
+                }
+            }
+            match glib::Regex::new(
+                link::FILE_PATTERN,
+                glib::RegexCompileFlags::MULTILINE,
+                glib::RegexMatchFlags::DEFAULT,
+            ) {
+                Err(e) => {
+                    glib::g_warning!(G_LOG_DOMAIN, "file regex failed: {}", e);
+                }
+                Ok(Some(regex)) => {
+                    self.file_regex.set(regex).unwrap();
+                }
+                Ok(None) => (),
+            }
+        }
+        pub fn set_link_modifier(&self, modifier: gdk::ModifierType) {
+            self.link_modifier.set(Some(modifier));
+        }
+        /// every link on screen as (row, column, text, link), rows counted from the top
+        fn visible_links(&self, terminal: &LayerConsoleTerminal) -> Vec<(i64, i64, String, Link)> {
+            let mut links: Vec<(i64, i64, String, Link)> = Vec::new();
+            let columns = terminal.column_count();
+            let rows = terminal.row_count();
+            let top = terminal
+                .vadjustment()
+                .map(|adjustment| adjustment.value() as i64)
+                .unwrap_or(0);
+            let link_regexes = self.link_regexes.borrow();
+            let matchers = self.matchers.borrow();
+
+            for row in 0..rows {
+                let mut found = |column: i64, text: String, link: Link| {
+                    if !links.iter().any(|(r, c, _, _)| *r == row && *c == column) {
+                        links.push((row, column, text, link));
+                    }
+                };
+
+                // hyperlinks are not part of the text, look for them cell by cell
+                let mut previous: Option<GString> = None;
+                for column in 0..columns {
+                    let (x, y) = terminal.cell_center(row, column);
+                    let hyperlink = terminal.check_hyperlink_at(x, y);
+                    if let Some(uri) = &hyperlink {
+                        if previous.as_ref() != Some(uri) {
+                            found(column, uri.to_string(), Link::Url(uri.to_string()));
+                        }
+                    }
+                    previous = hyperlink;
+                }
+
+                let Some((line, cells)) = terminal.row_text(top + row) else {
+                    continue;
+                };
+                let line = GString::from(line);
+                let column_of =
+                    |offset: i32| cells.get(offset as usize).copied().unwrap_or_default();
+                for regex in link_regexes.iter() {
+                    for_each_match(regex, line.as_gstr(), |match_info| {
+                        let (Some(text), Some((start, _))) =
+                            (match_info.fetch(0), match_info.fetch_pos(0))
+                        else {
+                            return;
+                        };
+                        let link = Link::Url(link::normalize_url(&text));
+                        found(column_of(start), text.to_string(), link);
+                    });
+                }
+                if let Some(regex) = self.file_regex.get() {
+                    for_each_match(regex, line.as_gstr(), |match_info| {
+                        let (Some(text), Some((start, _))) =
+                            (match_info.fetch(0), match_info.fetch_pos(0))
+                        else {
+                            return;
+                        };
+                        if let Some(link) = self.file_link_from_match(match_info) {
+                            found(column_of(start), text.to_string(), link);
+                        }
+                    });
+                }
+                for matcher in matchers.iter() {
+                    for_each_match(&matcher.regex, line.as_gstr(), |match_info| {
+                        let (Some(text), Some((start, _))) =
+                            (match_info.fetch(0), match_info.fetch_pos(0))
+                        else {
+                            return;
+                        };
+                        found(
+                            column_of(start),
+                            text.to_string(),
+                            matcher.expand(match_info),
+                        );
+                    });
+                }
+            }
+            links
+        }
+        pub fn start_hints(&self, action: HintAction) {
+            self.cancel_hints();
+            let Some(terminal) = self.terminal() else {
+                return;
+            };
+            let links = self.visible_links(&terminal);
+            if links.is_empty() {
+                self.obj().error_bell();
+                return;
+            }
+            let labels = hints::labels(links.len());
+            let mut hints = Vec::with_capacity(links.len());
+            for ((row, column, text, link), label) in links.into_iter().zip(labels) {
+                let (x, y) = terminal.cell_origin(row, column);
+                let point = terminal
+                    .compute_point(
+                        &self.hint_overlay,
+                        &graphene::Point::new(x as f32, y as f32),
+                    )
+                    .unwrap_or_else(|| graphene::Point::new(0.0, 0.0));
+                let widget = gtk::Label::new(Some(&label));
+                widget.add_css_class("hint");
+                widget.set_halign(gtk::Align::Start);
+                widget.set_valign(gtk::Align::Start);
+                widget.set_margin_start(point.x() as i32);
+                widget.set_margin_top(point.y() as i32);
+                self.hint_overlay.add_overlay(&widget);
+                hints.push(Hint {
+                    label,
+                    text,
+                    link,
+                    widget,
+                });
+            }
+            self.hints.replace(hints);
+            self.hint_input.borrow_mut().clear();
+            self.hint_action.set(action);
+            // labels would point to the wrong text once it scrolls
+            if let Some(adjustment) = terminal.vadjustment() {
+                let handler = adjustment.connect_value_changed(glib::clone!(
+                    #[weak(rename_to = this)]
+                    self,
+                    move |_| this.cancel_hints()
+                ));
+                self.hint_scroll_handler
+                    .replace(Some((adjustment, handler)));
+            }
+        }
+        fn cancel_hints(&self) {
+            if let Some((adjustment, handler)) = self.hint_scroll_handler.take() {
+                adjustment.disconnect(handler);
+            }
+            for hint in self.hints.take() {
+                self.hint_overlay.remove_overlay(&hint.widget);
+            }
+            self.hint_input.borrow_mut().clear();
+        }
+        /// handle a key typed in hint mode, returns whether hint mode is active
+        fn hint_key(&self, key: gdk::Key) -> bool {
+            if self.hints.borrow().is_empty() {
+                return false;
+            }
+            match key {
+                gdk::Key::Escape => {
+                    self.cancel_hints();
+                    return true;
+                }
+                gdk::Key::BackSpace => {
+                    self.hint_input.borrow_mut().pop();
+                }
+                _ => match key.to_unicode() {
+                    Some(c) if c.is_alphanumeric() => {
+                        self.hint_input.borrow_mut().push(c.to_ascii_lowercase())
+                    }
+                    _ => return true,
+                },
+            }
+            let input = self.hint_input.borrow().clone();
+            let chosen = self
+                .hints
+                .borrow()
+                .iter()
+                .find(|hint| hint.label == input)
+                .map(|hint| (hint.text.clone(), hint.link.clone()));
+            if let Some((text, link)) = chosen {
+                self.cancel_hints();
+                match self.hint_action.get() {
+                    HintAction::Open => self.open_link(&link),
+                    HintAction::Copy => self.copy_link(&text),
+                }
+                return true;
+            }
+            let mut any = false;
+            for hint in self.hints.borrow().iter() {
+                let visible = hint.label.starts_with(&input);
+                hint.widget.set_visible(visible);
+                any |= visible;
+            }
+            if !any {
+                self.cancel_hints();
+            }
+            true
+        }
+        fn setup_hints(&self) {
+            let controller = gtk::EventControllerKey::new();
+            controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+            controller.connect_key_pressed(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                #[upgrade_or]
+                glib::Propagation::Proceed,
+                move |_, key, _, _| {
+                    if this.hint_key(key) {
+                        glib::Propagation::Stop
+                    } else {
+                        glib::Propagation::Proceed
+                    }
+                }
+            ));
+            self.obj().add_controller(controller);
+        }
+        pub fn set_url_handler(&self, scheme: &str, command: &str) {
+            self.url_handlers
+                .borrow_mut()
+                .insert(scheme.to_lowercase(), command.to_string());
+        }
+        /// go back to the default handlers
+        pub fn reset_url_handlers(&self) {
+            self.url_handlers.replace(HashMap::from([(
+                "ssh".to_string(),
+                DEFAULT_SSH_HANDLER.to_string(),
+            )]));
+        }
+        fn open_link(&self, link: &Link) {
+            let opened = match link {
+                Link::Url(url) => self.open_url(url),
+                Link::Command(command) => self.run_command(command).then_some(Opened::Outside),
+                Link::File { path, line, column } => self.open_file(path, *line, *column),
+            };
+            if opened == Some(Opened::Outside) && self.hide_on_open.get() {
+                self.hide();
+            }
+        }
+        fn open_file(
+            &self,
+            path: &std::path::Path,
+            line: Option<u32>,
+            column: Option<u32>,
+        ) -> Option<Opened> {
+            let editor = self.editor.borrow().clone();
+            let Some(editor) = editor else {
+                return match glib::filename_to_uri(path, None) {
+                    Ok(uri) => self.open_url(&uri),
+                    Err(e) => {
+                        glib::g_warning!(
+                            G_LOG_DOMAIN,
+                            "invalid file path ({}): {}",
+                            path.display(),
+                            e
+                        );
+                        None
+                    }
+                };
+            };
+            let path = path.to_string_lossy();
+            let line = line.unwrap_or(1).to_string();
+            let column = column.unwrap_or(1).to_string();
+            let command = link::expand_command(
+                &editor,
+                &[
+                    Some(path.as_ref()),
+                    Some(path.as_ref()),
+                    Some(line.as_str()),
+                    Some(column.as_str()),
+                ],
+            );
+            self.run_command(&command).then_some(Opened::Outside)
+        }
+        fn run_command(&self, command: &str) -> bool {
+            let launcher = gio::SubprocessLauncher::new(gio::SubprocessFlags::NONE);
+            if let Some(directory) = self.spawn_directory() {
+                launcher.set_cwd(directory);
+            }
+            if let Err(e) = launcher.spawn(&["/bin/sh".as_ref(), "-c".as_ref(), command.as_ref()]) {
+                glib::g_warning!(G_LOG_DOMAIN, "failed to run command ({}): {}", command, e);
+                return false;
+            }
+            true
+        }
+        fn open_url(&self, url: &str) -> Option<Opened> {
+            let handler = glib::Uri::peek_scheme(url)
+                .and_then(|scheme| self.url_handlers.borrow().get(scheme.as_str()).cloned())
+                // an empty handler leaves the scheme to the opener
+                .filter(|handler| !handler.is_empty());
+            if let Some(handler) = handler {
+                let (destination, port) = link::url_destination(url);
+                // `$0` and `$1` both stand for the link
+                let captures = [
+                    Some(url),
+                    Some(url),
+                    destination.as_deref(),
+                    port.as_deref(),
+                ];
+                if let Some(handler) = handler.strip_prefix("tab:") {
+                    let command = link::expand_command(handler, &captures);
+                    self.run_in_new_tab(&["/bin/sh", "-c", &command]);
+                    return Some(Opened::Tab);
+                }
+                let command = link::expand_command(&handler, &captures);
+                return self.run_command(&command).then_some(Opened::Outside);
+            }
+            if let Some(opener) = self.opener.borrow().clone() {
+                let command = link::expand_command(&opener, &[Some(url), Some(url)]);
+                return self.run_command(&command).then_some(Opened::Outside);
+            }
+            if let Err(e) = AppInfo::launch_default_for_uri(url, AppLaunchContext::NONE) {
+                glib::g_warning!(
+                    G_LOG_DOMAIN,
+                    "failed to launch app for uri ({}): {}",
+                    url,
+                    e
+                );
+                return None;
+            }
+            Some(Opened::Outside)
+        }
+        fn paste_from(&self, clipboard: gdk::Clipboard) {
+            clipboard.read_text_async(
                 gio::Cancellable::NONE,
-                |_| {},
+                glib::clone!(
+                    #[weak(rename_to = this)]
+                    self,
+                    move |result| match result {
+                        Ok(Some(text)) => this.paste_text(&text),
+                        Ok(None) => (),
+                        Err(e) => {
+                            glib::g_warning!(G_LOG_DOMAIN, "failed to read clipboard: {}", e);
+                        }
+                    }
+                ),
             );
         }
-        fn get_url(&self, x: f64, y: f64) -> Option<GString> {
-            if let Some(hyperlink) = self.terminal.check_hyperlink_at(x, y) {
-                return Some(hyperlink);
-            } else if let (Some(url), id) = self.terminal.check_match_at(x, y) {
-                if self.match_ids.borrow().contains(&id) {
-                    return Some(url);
-                }
+        pub fn paste(&self) {
+            self.paste_from(self.obj().clipboard());
+        }
+        pub fn paste_selection(&self) {
+            self.paste_from(self.obj().primary_clipboard());
+        }
+        fn paste_text(&self, text: &str) {
+            if self.confirm_paste.get() {
+                if let Some(reason) = unsafe_paste_reason(text) {
+                    self.paste_label
+                        .set_text(&format!("Paste text {} into the console?", reason));
+                    self.pending_paste.replace(Some(text.to_string()));
+                    self.paste_revealer.set_reveal_child(true);
+                    self.paste_cancel_button.grab_focus();
+                    return;
+                }
+            }
+            if let Some(terminal) = self.terminal() {
+                terminal.paste_text(text);
+            }
+        }
+        fn finish_paste(&self, accepted: bool) {
+            self.paste_revealer.set_reveal_child(false);
+            let Some(terminal) = self.terminal() else {
+                return;
+            };
+            if let Some(text) = self.pending_paste.take() {
+                if accepted {
+                    terminal.paste_text(&text);
+                }
+            }
+            terminal.grab_focus();
+        }
+        fn setup_paste_confirmation(&self) {
+            let bar = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+            bar.add_css_class("paste-confirmation");
+            self.paste_label.set_hexpand(true);
+            self.paste_label.set_xalign(0.0);
+            self.paste_label.set_wrap(true);
+            bar.append(&self.paste_label);
+
+            self.paste_cancel_button.set_label("Cancel");
+            self.paste_cancel_button.connect_clicked(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| this.finish_paste(false)
+            ));
+            bar.append(&self.paste_cancel_button);
+
+            let button = gtk::Button::with_label("Paste");
+            button.add_css_class("destructive-action");
+            button.connect_clicked(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| this.finish_paste(true)
+            ));
+            bar.append(&button);
+
+            let controller = gtk::EventControllerKey::new();
+            controller.connect_key_pressed(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                #[upgrade_or]
+                glib::Propagation::Proceed,
+                move |_, key, _, _| {
+                    if key == gdk::Key::Escape {
+                        this.finish_paste(false);
+                        return glib::Propagation::Stop;
+                    }
+                    glib::Propagation::Proceed
+                }
+            ));
+            bar.add_controller(controller);
+
+            self.paste_revealer.set_child(Some(&bar));
+        }
+        /// show `errors` of the config file above the terminal until dismissed
+        pub fn show_config_errors(&self, errors: &[String]) {
+            if errors.is_empty() {
+                self.config_errors_revealer.set_reveal_child(false);
+                return;
+            }
+            let mut text =
+                "Errors in the config file, the settings they concern are left as they were:"
+                    .to_string();
+            for error in errors {
+                text.push_str("\n• ");
+                text.push_str(error);
+            }
+            self.config_errors_label.set_text(&text);
+            self.config_errors_revealer.set_reveal_child(true);
+        }
+        fn setup_config_errors(&self) {
+            let bar = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+            bar.add_css_class("config-errors");
+            self.config_errors_label.set_hexpand(true);
+            self.config_errors_label.set_xalign(0.0);
+            self.config_errors_label.set_wrap(true);
+            self.config_errors_label.set_selectable(true);
+            bar.append(&self.config_errors_label);
+
+            let button = gtk::Button::with_label("Dismiss");
+            button.set_valign(gtk::Align::Start);
+            button.connect_clicked(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| {
+                    this.config_errors_revealer.set_reveal_child(false);
+                    if let Some(terminal) = this.terminal() {
+                        terminal.grab_focus();
+                    }
+                }
+            ));
+            bar.append(&button);
+
+            self.config_errors_revealer.set_child(Some(&bar));
+        }
+        fn copy_link(&self, url: &str) {
+            self.obj().clipboard().set_text(url);
+        }
+        fn open_preferences(&self) {
+            let Some(path) = self.config_file.borrow().clone() else {
+                return;
+            };
+            if !path.exists() {
+                // give the user an empty file to start from
+                let created = match path.parent() {
+                    Some(parent) => std::fs::create_dir_all(parent),
+                    None => Ok(()),
+                }
+                .and_then(|_| std::fs::File::create(&path).map(|_| ()));
+                if let Err(e) = created {
+                    glib::g_warning!(
+                        G_LOG_DOMAIN,
+                        "failed to create config file ({}): {}",
+                        path.display(),
+                        e
+                    );
+                    return;
+                }
+            }
+            match glib::filename_to_uri(&path, None) {
+                Ok(uri) => {
+                    self.open_url(&uri);
+                }
+                Err(e) => {
+                    glib::g_warning!(
+                        G_LOG_DOMAIN,
+                        "invalid config file path ({}): {}",
+                        path.display(),
+                        e
+                    );
+                    return;
+                }
+            }
+            // the editor opens below the layer surface, get out of its way
+            self.hide();
+        }
+        fn set_action_enabled(&self, name: &str, enabled: bool) {
+            if let Some(action) = self.obj().lookup_action(name) {
+                action
+                    .downcast::<SimpleAction>()
+                    .unwrap()
+                    .set_enabled(enabled);
+            }
+        }
+        /// absolute row at `y` in the terminal's coordinates
+        fn row_at(&self, terminal: &LayerConsoleTerminal, y: f64) -> i64 {
+            let top = terminal
+                .vadjustment()
+                .map(|adjustment| adjustment.value() as i64)
+                .unwrap_or(0);
+            top + (y / terminal.char_height() as f64).floor() as i64
+        }
+        /// copy the output of the command under the context menu, or of the last one
+        fn copy_output(&self) {
+            let output = self
+                .terminal()
+                .and_then(|terminal| terminal.command_output(self.context_row.get()));
+            match output {
+                Some(output) => self.obj().clipboard().set_text(&output),
+                None => self.obj().error_bell(),
+            }
+        }
+        /// select and copy the output of the command under the context menu, or of the last one
+        fn select_output(&self) {
+            let selected = self
+                .terminal()
+                .is_some_and(|terminal| terminal.select_output(self.context_row.get()));
+            if !selected {
+                self.obj().error_bell();
+            }
+        }
+        fn popup_context_menu(&self, terminal: &LayerConsoleTerminal, x: f64, y: f64) {
+            let link = self.get_link(terminal, x, y);
+            self.set_action_enabled("open-link", link.is_some());
+            self.set_action_enabled(
+                "copy-link",
+                matches!(link, Some(Link::Url(_) | Link::File { .. })),
+            );
+            self.context_link.replace(link);
+            let row = self.row_at(terminal, y);
+            let has_output = terminal.command_output(Some(row)).is_some();
+            self.set_action_enabled("copy-output", has_output);
+            self.set_action_enabled("select-output", has_output);
+            self.context_row.set(Some(row));
+            let has_selection = terminal.has_selection();
+            self.set_action_enabled(
+                "copy",
+                has_selection || terminal.selected_output().is_some(),
+            );
+            self.set_action_enabled("copy-html", has_selection);
+
+            // the menu belongs to the overlay, shared by all the tabs
+            let point = terminal
+                .compute_point(
+                    &self.hint_overlay,
+                    &graphene::Point::new(x as f32, y as f32),
+                )
+                .unwrap_or_else(|| graphene::Point::new(0.0, 0.0));
+            let popover = self.context_menu.get().unwrap();
+            popover.set_pointing_to(Some(&gdk::Rectangle::new(
+                point.x() as i32,
+                point.y() as i32,
+                1,
+                1,
+            )));
+            popover.popup();
+        }
+        fn setup_context_menu(&self) {
+            let menu = gio::Menu::new();
+
+            let section = gio::Menu::new();
+            section.append(Some("Copy"), Some("win.copy"));
+            section.append(Some("Copy as HTML"), Some("win.copy-html"));
+            section.append(Some("Copy Output"), Some("win.copy-output"));
+            section.append(Some("Select Output"), Some("win.select-output"));
+            section.append(Some("Paste"), Some("win.paste"));
+            menu.append_section(None, &section);
+
+            let section = gio::Menu::new();
+            section.append(Some("Open Link"), Some("win.open-link"));
+            section.append(Some("Copy Link"), Some("win.copy-link"));
+            menu.append_section(None, &section);
+
+            let section = gio::Menu::new();
+            section.append(Some("Select All"), Some("win.select-all"));
+            section.append(Some("Search"), Some("win.search"));
+            menu.append_section(None, &section);
+
+            let section = gio::Menu::new();
+            section.append(Some("Fullscreen"), Some("win.fullscreen"));
+            let move_to = gio::Menu::new();
+            for (label, position) in [
+                ("Top", Position::Top),
+                ("Bottom", Position::Bottom),
+                ("Left", Position::Left),
+                ("Right", Position::Right),
+            ] {
+                move_to.append(
+                    Some(label),
+                    Some(&format!("win.move-to::{}", position.name())),
+                );
+            }
+            section.append_submenu(Some("Move to"), &move_to);
+            section.append(Some("Preferences"), Some("win.preferences"));
+            menu.append_section(None, &section);
+
+            let popover = gtk::PopoverMenu::from_model(Some(&menu));
+            popover.set_parent(&self.hint_overlay);
+            popover.set_has_arrow(false);
+            popover.set_halign(gtk::Align::Start);
+            popover.connect_closed(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| {
+                    // actions are also reachable through accelerators
+                    this.set_action_enabled("copy", true);
+                    this.set_action_enabled("copy-html", true);
+                    this.set_action_enabled("copy-output", true);
+                    this.set_action_enabled("select-output", true);
+                    this.context_row.set(None);
+                }
+            ));
+            self.context_menu.set(popover).unwrap();
+        }
+        fn update_search(&self) {
+            let Some(terminal) = self.terminal() else {
+                return;
+            };
+            let text = self.search_entry.text();
+            if text.is_empty() {
+                terminal.search_set_regex(None, 0);
+                return;
+            }
+            // smart case: only care about case when the pattern has upper case letters
+            let mut flags = PCRE2_MULTILINE;
+            if !text.chars().any(char::is_uppercase) {
+                flags |= PCRE2_CASELESS;
+            }
+            match vte4::Regex::for_search(&glib::Regex::escape_string(&text), flags) {
+                Err(e) => {
+                    glib::g_warning!(G_LOG_DOMAIN, "search regex failed: {}", e);
+                }
+                Ok(regex) => {
+                    terminal.search_set_regex(Some(&regex), 0);
+                    terminal.search_find_previous();
+                }
+            }
+        }
+        fn setup_search(&self) {
+            self.search_bar.set_child(Some(&self.search_entry));
+            self.search_bar.connect_entry(&self.search_entry);
+            self.search_bar.set_show_close_button(true);
+
+            self.search_entry.connect_search_changed(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| this.update_search()
+            ));
+            self.search_entry.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| {
+                    if let Some(terminal) = this.terminal() {
+                        terminal.search_find_previous();
+                    }
+                }
+            ));
+            self.search_entry.connect_previous_match(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| {
+                    if let Some(terminal) = this.terminal() {
+                        terminal.search_find_previous();
+                    }
+                }
+            ));
+            self.search_entry.connect_next_match(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| {
+                    if let Some(terminal) = this.terminal() {
+                        terminal.search_find_next();
+                    }
+                }
+            ));
+            self.search_entry.connect_stop_search(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| this.search_bar.set_search_mode(false)
+            ));
+            self.search_bar
+                .connect_search_mode_enabled_notify(glib::clone!(
+                    #[weak(rename_to = this)]
+                    self,
+                    move |search_bar| {
+                        if search_bar.is_search_mode() {
+                            this.search_entry.grab_focus();
+                        } else {
+                            for terminal in this.terminals() {
+                                terminal.search_set_regex(None, 0);
+                            }
+                            if let Some(terminal) = this.terminal() {
+                                terminal.grab_focus();
+                            }
+                        }
+                    }
+                ));
+        }
+        fn update_current_directory(&self) {
+            let directory = self
+                .terminal()
+                .and_then(|terminal| terminal.current_directory());
+            if *self.current_directory.borrow() == directory {
+                return;
             }
-            None
+            self.current_directory.replace(directory);
+            self.obj().notify_current_directory();
+            self.update_title();
+            self.state_changed();
         }
-        fn open_url(&self, url: &str) {
-            if let Err(e) = AppInfo::launch_default_for_uri(url, AppLaunchContext::NONE) {
-                glib::g_warning!(
-                    G_LOG_DOMAIN,
-                    "failed to launch app for uri ({}): {}",
-                    url,
-                    e
-                );
-            }
+        fn update_title(&self) {
+            let title = match self.current_directory.borrow().as_deref() {
+                Some(directory) => format!("layer-console: {}", directory),
+                None => "layer-console".to_string(),
+            };
+            self.obj().set_title(Some(&title));
         }
         fn connect_signals(&self) {
-            let window = self.obj();
-            self.terminal.connect_child_exited(glib::clone!(
-                #[weak]
-                window,
-                move |_vte, _status| window.close()
+            self.notebook.connect_page_notify(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_notebook| this.on_tab_switched()
             ));
+            // tabs are only shown when there is more than one
+            let update_tabs = |notebook: &gtk::Notebook, _page: &gtk::Widget, _index: u32| {
+                notebook.set_show_tabs(notebook.n_pages() > 1);
+            };
+            self.notebook.connect_page_added(update_tabs);
+            self.notebook.connect_page_removed(update_tabs);
             self.stack.connect_transition_running_notify(|stack| {
                 if !stack.is_transition_running()
                     && stack.visible_child_name() == Some(GString::from("empty"))
@@ -206,6 +2079,15 @@ mod imp {
                     stack.parent().unwrap().set_visible(false);
                 }
             });
+            self.obj().connect_is_active_notify(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |window| {
+                    if window.is_active() {
+                        this.set_urgent(false);
+                    }
+                }
+            ));
             let stack = self.stack.clone();
             self.obj().connect_show(glib::clone!(
                 #[weak(rename_to = this)]
@@ -213,6 +2095,7 @@ mod imp {
                 #[weak]
                 stack,
                 move |_| {
+                    this.notified_while_hidden.set(false);
                     let transition_type = match this.position.get() {
                         Position::Top => gtk::StackTransitionType::SlideDown,
                         Position::Bottom => gtk::StackTransitionType::SlideUp,
@@ -232,7 +2115,75 @@ mod imp {
                 #[weak(rename_to = this)]
                 self,
                 move |_action, _parameter| {
-                    this.terminal.copy_clipboard_format(vte4::Format::Text);
+                    let Some(terminal) = this.terminal() else {
+                        return;
+                    };
+                    match terminal.selected_output() {
+                        Some(output) => this.obj().clipboard().set_text(&output),
+                        None => terminal.copy_clipboard_format(vte4::Format::Text),
+                    }
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("copy-html", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    if let Some(terminal) = this.terminal() {
+                        terminal.copy_clipboard_format(vte4::Format::Html);
+                    }
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("copy-output", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    this.copy_output();
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("select-output", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    this.select_output();
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("previous-prompt", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    let scrolled = this
+                        .terminal()
+                        .is_some_and(|terminal| terminal.scroll_to_prompt(false));
+                    if !scrolled {
+                        this.obj().error_bell();
+                    }
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("next-prompt", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    let scrolled = this
+                        .terminal()
+                        .is_some_and(|terminal| terminal.scroll_to_prompt(true));
+                    if !scrolled {
+                        this.obj().error_bell();
+                    }
                 }
             ));
             window.add_action(&action);
@@ -242,7 +2193,144 @@ mod imp {
                 #[weak(rename_to = this)]
                 self,
                 move |_action, _parameter| {
-                    this.terminal.paste_clipboard();
+                    this.paste();
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("paste-selection", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    this.paste_selection();
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("open-link", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    let link = this.context_link.borrow().clone();
+                    if let Some(link) = link {
+                        this.open_link(&link);
+                    }
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("copy-link", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    let link = this.context_link.borrow().clone();
+                    match link {
+                        Some(Link::Url(url)) => this.copy_link(&url),
+                        Some(Link::File { path, .. }) => this.copy_link(&path.to_string_lossy()),
+                        _ => (),
+                    }
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("hints", Some(glib::VariantTy::STRING));
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, parameter| {
+                    let name = parameter.and_then(|p| p.str()).unwrap_or_default();
+                    match HintAction::from_name(name) {
+                        Some(action) => this.start_hints(action),
+                        None => {
+                            glib::g_warning!(G_LOG_DOMAIN, "unknown hint action: {}", name);
+                        }
+                    }
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("select-all", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    if let Some(terminal) = this.terminal() {
+                        terminal.select_all();
+                    }
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("search", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    this.search_bar
+                        .set_search_mode(!this.search_bar.is_search_mode());
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("preferences", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    this.open_preferences();
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new_stateful(
+                "move-to",
+                Some(glib::VariantTy::STRING),
+                &self.position.get().name().to_variant(),
+            );
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, parameter| {
+                    let name = parameter.and_then(|p| p.str()).unwrap_or_default();
+                    match Position::from_name(name) {
+                        Some(position) => this.obj().set_position(position),
+                        None => {
+                            glib::g_warning!(G_LOG_DOMAIN, "unknown position: {}", name);
+                        }
+                    }
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("zoom-in", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    this.zoom_in();
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("zoom-out", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    this.zoom_out();
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("zoom-reset", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    this.set_font_scale(1.0);
                 }
             ));
             window.add_action(&action);
@@ -256,6 +2344,65 @@ mod imp {
                 }
             ));
             window.add_action(&action);
+
+            let action = SimpleAction::new("new-tab", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    this.new_tab();
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("close-tab", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    if let Some(pane) = this.current_tmux_pane() {
+                        if let Some(controller) = this.tmux.borrow().as_ref() {
+                            controller.run(&format!("kill-pane -t %{}", pane));
+                        }
+                        return;
+                    }
+                    if let Some(terminal) = this.terminal() {
+                        terminal.close_daemon_session();
+                        this.close_tab(&terminal);
+                    }
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("next-tab", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    let notebook = &this.notebook;
+                    if notebook.current_page() == Some(notebook.n_pages().saturating_sub(1)) {
+                        notebook.set_current_page(Some(0));
+                    } else {
+                        notebook.next_page();
+                    }
+                }
+            ));
+            window.add_action(&action);
+
+            let action = SimpleAction::new("previous-tab", None);
+            action.connect_activate(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_action, _parameter| {
+                    let notebook = &this.notebook;
+                    if notebook.current_page() == Some(0) {
+                        notebook.set_current_page(None);
+                    } else {
+                        notebook.prev_page();
+                    }
+                }
+            ));
+            window.add_action(&action);
         }
         pub fn set_anchors(&self) {
             let window = self.obj();
@@ -267,7 +2414,7 @@ mod imp {
                 }
             }
         }
-        fn set_terminal_colors(&self) {
+        fn set_terminal_colors(&self, terminal: &LayerConsoleTerminal) {
             // color scheme from alacritty
             let foreground = RGBA::parse("#d8d8d8").unwrap();
             let mut background = RGBA::parse("#181818").unwrap();
@@ -290,20 +2437,27 @@ mod imp {
                 &RGBA::parse("#93d3c3").unwrap(), // Bright Cyan
                 &RGBA::parse("#f8f8f8").unwrap(), // Bright White
             ];
-            self.terminal
-                .set_colors(Some(&foreground), Some(&background), &pallet);
+            terminal.set_colors(Some(&foreground), Some(&background), &pallet);
+        }
+        fn hide(&self) {
+            if !self.obj().is_visible()
+                || self.stack.visible_child_name() == Some(GString::from("empty"))
+            {
+                return;
+            }
+            let transition_type = match self.position.get() {
+                Position::Top => gtk::StackTransitionType::SlideUp,
+                Position::Bottom => gtk::StackTransitionType::SlideDown,
+                Position::Left => gtk::StackTransitionType::SlideLeft,
+                Position::Right => gtk::StackTransitionType::SlideRight,
+            };
+            self.stack.set_transition_type(transition_type);
+            self.stack.set_visible_child_name("empty");
         }
         pub fn toggle(&self) {
             let window = self.obj();
             if window.is_visible() {
-                let transition_type = match self.position.get() {
-                    Position::Top => gtk::StackTransitionType::SlideUp,
-                    Position::Bottom => gtk::StackTransitionType::SlideDown,
-                    Position::Left => gtk::StackTransitionType::SlideLeft,
-                    Position::Right => gtk::StackTransitionType::SlideRight,
-                };
-                self.stack.set_transition_type(transition_type);
-                self.stack.set_visible_child_name("empty");
+                self.hide();
             } else {
                 window.present();
             }
@@ -319,11 +2473,16 @@ mod imp {
 
     #[glib::derived_properties]
     impl ObjectImpl for LayerConsoleWindow {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            SIGNALS.get_or_init(|| vec![Signal::builder("state-changed").build()])
+        }
         fn constructed(&self) {
             self.parent_constructed();
 
             self.connect_signals();
             self.setup_actions();
+            self.reset_url_handlers();
 
             let window = self.obj();
             window.init_layer_shell();
@@ -331,71 +2490,62 @@ mod imp {
             self.set_anchors();
             self.set_css_class();
             window.set_keyboard_mode(KeyboardMode::OnDemand);
+            self.update_title();
 
             let empty = gtk::Box::new(gtk::Orientation::Vertical, 0);
             self.stack.add_named(&empty, Some("empty"));
             self.stack.set_transition_duration(300);
             window.set_child(Some(&self.stack));
 
-            let scrolled = gtk::ScrolledWindow::builder()
-                .vexpand(true)
-                .propagate_natural_height(true)
-                .propagate_natural_width(true)
-                .hscrollbar_policy(gtk::PolicyType::Never)
-                .build();
-            scrolled.set_child(Some(&self.terminal));
-            self.stack.add_named(&scrolled, Some("terminal"));
+            self.notebook.set_show_tabs(false);
+            self.notebook.set_show_border(false);
+            self.notebook.set_scrollable(true);
+            self.setup_search();
+            let content = gtk::Box::new(gtk::Orientation::Vertical, 0);
+            self.setup_config_errors();
+            content.append(&self.config_errors_revealer);
+            self.setup_paste_confirmation();
+            content.append(&self.paste_revealer);
+            self.hint_overlay.set_child(Some(&self.notebook));
+            self.setup_hints();
+            content.append(&self.hint_overlay);
+            content.append(&self.search_bar);
+            self.stack.add_named(&content, Some("terminal"));
+            self.setup_context_menu();
 
-            self.terminal.set_size(DEFAULT_COLUMNS, DEFAULT_ROWS);
+            self.font_scale.set(1.0);
+            self.set_terminal_size(Some(DEFAULT_COLUMNS), Some(DEFAULT_ROWS));
             self.set_font(DEFAULT_FONT);
-            self.set_terminal_colors();
-            self.terminal.set_bold_is_bright(true);
-            self.terminal.grab_focus();
-            self.terminal.set_allow_hyperlink(true);
+            self.middle_click_paste.set(true);
+            self.confirm_paste.set(true);
+            self.notify_min_duration.set(DEFAULT_NOTIFY_MIN_DURATION);
+            self.scrollback_save_limit
+                .set(DEFAULT_SCROLLBACK_SAVE_LIMIT);
 
             for l in LINKS {
                 match vte4::Regex::for_match(l, PCRE2_MULTILINE) {
                     Err(e) => {
                         glib::g_warning!(G_LOG_DOMAIN, "link regex failed: {}", e);
                     }
-                    Ok(regex) => {
-                        let id = self.terminal.match_add_regex(&regex, 0);
-                        self.terminal.match_set_cursor_name(id, "pointer");
-                        self.match_ids.borrow_mut().insert(id);
-                    }
+                    Ok(regex) => self.link_vte_regexes.borrow_mut().push(regex),
                 }
-            }
-            let gesture = gtk::GestureClick::new();
-            gesture.set_button(1);
-            gesture.connect_pressed(glib::clone!(
-                #[weak(rename_to = this)]
-                self,
-                move |gesture, n, x, y| {
-                    if n > 1 {
-                        gesture.set_state(gtk::EventSequenceState::Denied);
-                        return;
-                    }
-                    if !gesture
-                        .current_event_state()
-                        .contains(gdk::ModifierType::CONTROL_MASK)
-                    {
-                        gesture.set_state(gtk::EventSequenceState::Denied);
-                        return;
-                    }
-                    if let Some(url) = this.get_url(x, y) {
-                        this.open_url(&url);
-                        gesture.set_state(gtk::EventSequenceState::Claimed);
-                        return;
+                match glib::Regex::new(
+                    l,
+                    glib::RegexCompileFlags::MULTILINE,
+                    glib::RegexMatchFlags::DEFAULT,
+                ) {
+                    Err(e) => {
+                        glib::g_warning!(G_LOG_DOMAIN, "link regex failed: {}", e);
                     }
-                    gesture.set_state(gtk::EventSequenceState::Denied);
+                    Ok(regex) => self.link_regexes.borrow_mut().extend(regex),
                 }
-            ));
-            self.terminal.add_controller(gesture);
-
-            self.terminal
-                .connect_hyperlink_hover_uri_notify(|terminal| {
-                    terminal.set_tooltip_text(terminal.hyperlink_hover_uri().as_deref());
-                });
+            }
+            self.setup_file_matches();
+        }
+        fn dispose(&self) {
+            if let Some(popover) = self.context_menu.get() {
+                popover.unparent();
+            }
         }
     }
     impl WidgetImpl for LayerConsoleWindow {}
@@ -416,16 +2566,93 @@ impl LayerConsoleWindow {
     pub fn toggle(&self) {
         self.imp().toggle();
     }
-    pub fn spawn(&self, args: &[&str]) {
-        self.imp().spawn(args)
+    pub fn add_tab(
+        &self,
+        args: &[&str],
+        directory: Option<&str>,
+        title: Option<&str>,
+        env: &[&str],
+        login_shell: bool,
+    ) -> LayerConsoleTerminal {
+        self.imp().add_tab(args, directory, title, env, login_shell)
+    }
+    /// terminals of the tabs, in order
+    pub fn terminals(&self) -> Vec<LayerConsoleTerminal> {
+        self.imp().terminals()
+    }
+    pub fn attach_tmux(&self, session: &str) -> Result<(), String> {
+        self.imp().attach_tmux(session)
+    }
+    pub fn attach_tab<F: FnOnce(&LayerConsoleTerminal, String) + 'static>(
+        &self,
+        id: u64,
+        title: Option<&str>,
+        on_error: F,
+    ) -> LayerConsoleTerminal {
+        self.imp().attach_tab(id, title, on_error)
+    }
+    /// remove the tab of `terminal`, closing the console with the last one
+    pub fn close_tab(&self, terminal: &LayerConsoleTerminal) {
+        self.imp().close_tab(terminal);
+    }
+    pub fn current_tab(&self) -> u32 {
+        self.imp().current_tab()
+    }
+    pub fn set_current_tab(&self, index: u32) {
+        self.imp().set_current_tab(index);
     }
     pub fn set_font(&self, font: &str) {
         self.imp().set_font(font);
     }
+    pub fn shell_command(&self) -> Vec<String> {
+        self.imp().shell_command()
+    }
     pub fn set_terminal_size(&self, columns: Option<i64>, rows: Option<i64>) {
         self.imp().set_terminal_size(columns, rows);
     }
+    /// size of the console in cells of the unscaled font
+    pub fn terminal_size(&self) -> (i64, i64) {
+        self.imp().terminal_size()
+    }
+    pub fn connect_state_changed<F: Fn(&Self) + 'static>(&self, f: F) -> glib::SignalHandlerId {
+        self.connect_closure(
+            "state-changed",
+            false,
+            glib::closure_local!(move |win: &Self| f(win)),
+        )
+    }
     pub fn fullscreen(&self) {
         self.imp().fullscreen();
     }
+    pub fn add_matcher(&self, pattern: &str, template: Template) -> Result<(), glib::Error> {
+        self.imp().add_matcher(pattern, template)
+    }
+    pub fn clear_matchers(&self) {
+        self.imp().clear_matchers();
+    }
+    pub fn show_config_errors(&self, errors: &[String]) {
+        self.imp().show_config_errors(errors);
+    }
+    pub fn set_url_handler(&self, scheme: &str, command: &str) {
+        self.imp().set_url_handler(scheme, command);
+    }
+    pub fn reset_url_handlers(&self) {
+        self.imp().reset_url_handlers();
+    }
+    pub fn set_link_modifier(&self, modifier: gdk::ModifierType) {
+        self.imp().set_link_modifier(modifier);
+    }
+    pub fn set_cursor_shape(&self, shape: vte4::CursorShape) {
+        self.imp().set_cursor_shape(shape);
+    }
+    pub fn set_cursor_blink_mode(&self, mode: vte4::CursorBlinkMode) {
+        self.imp().set_cursor_blink_mode(mode);
+    }
+    pub fn set_cursor_colors(
+        &self,
+        background: Option<&gdk::RGBA>,
+        foreground: Option<&gdk::RGBA>,
+    ) {
+        self.imp().set_cursor_colors(background, foreground);
+    }
 }
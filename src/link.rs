@@ -0,0 +1,179 @@
+use gtk::glib;
+use std::path::{Path, PathBuf};
+
+const PATHCHARS_CLASS: &str = "[-\\w.+@~]";
+
+/// file paths, either with a directory part or a `:line` suffix as printed by compilers.
+/// groups are the path, the line and the column.
+pub const FILE_PATTERN: &str = const_format::concatcp!(
+    "(?<![-\\w.+@~/:])(",
+    // absolute, or relative to the current, parent or home directory
+    "(?:~|\\.{1,2})?(?:/",
+    PATHCHARS_CLASS,
+    "+)+/?",
+    // relative with at least one directory
+    "|",
+    PATHCHARS_CLASS,
+    "+(?:/",
+    PATHCHARS_CLASS,
+    "+)+/?",
+    // bare file name followed by a line number
+    "|",
+    PATHCHARS_CLASS,
+    "+\\.\\w+(?=:\\d)",
+    ")(?::(\\d+)(?::(\\d+))?)?"
+);
+
+/// what a user defined matcher turns the text it matched into
+#[derive(Debug, Clone)]
+pub enum Template {
+    /// URL handed to the same opener as detected links
+    Url(String),
+    /// command line run by `/bin/sh -c`
+    Command(String),
+}
+
+/// something the user can activate in the terminal
+#[derive(Debug, Clone)]
+pub enum Link {
+    Url(String),
+    Command(String),
+    File {
+        path: PathBuf,
+        line: Option<u32>,
+        column: Option<u32>,
+    },
+}
+
+impl Template {
+    /// build the link for a match, `captures[0]` being the whole match
+    pub fn expand<S: AsRef<str>>(&self, captures: &[Option<S>]) -> Link {
+        match self {
+            Template::Url(template) => Link::Url(expand(template, captures, str::to_string)),
+            Template::Command(template) => Link::Command(expand_command(template, captures)),
+        }
+    }
+}
+
+/// expand a command line template, quoting the captures for the shell
+pub fn expand_command<S: AsRef<str>>(template: &str, captures: &[Option<S>]) -> String {
+    expand(template, captures, |s| {
+        glib::shell_quote(s).to_string_lossy().to_string()
+    })
+}
+
+/// replace `$N` and `${N}` with the capture groups, `$$` with `$`.
+/// anything else following `$` is kept as is so that the shell can expand it.
+fn expand<S: AsRef<str>>(
+    template: &str,
+    captures: &[Option<S>],
+    quote: impl Fn(&str) -> String,
+) -> String {
+    let capture = |index: &str| -> String {
+        match index.parse::<usize>() {
+            Ok(i) => captures
+                .get(i)
+                .and_then(|c| c.as_ref())
+                .map(AsRef::as_ref)
+                .map(&quote)
+                .unwrap_or_default(),
+            Err(_) => String::new(),
+        }
+    };
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = after;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end)
+                    if !braced[..end].is_empty()
+                        && braced[..end].bytes().all(|b| b.is_ascii_digit()) =>
+                {
+                    result.push_str(&capture(&braced[..end]));
+                    rest = &braced[end + 1..];
+                }
+                _ => result.push('$'),
+            }
+        } else {
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            if digits == 0 {
+                result.push('$');
+            } else {
+                result.push_str(&capture(&rest[..digits]));
+                rest = &rest[digits..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// host of `url` with its user, as in `user@host`, and its port, for the handlers of links
+pub fn url_destination(url: &str) -> (Option<String>, Option<String>) {
+    let Ok(uri) = glib::Uri::parse(url, glib::UriFlags::NONE) else {
+        return (None, None);
+    };
+    let destination = uri.host().map(|host| match uri.user() {
+        Some(user) => format!("{}@{}", user, host),
+        None => host.to_string(),
+    });
+    let port = Some(uri.port()).filter(|&port| port > 0);
+    (destination, port.map(|port| port.to_string()))
+}
+
+/// add the scheme the desktop needs to links detected without one
+pub fn normalize_url(text: &str) -> String {
+    const SCHEMES: [&str; 7] = [
+        "mailto:", "callto:", "h323:", "sip:", "news:", "man:", "info:",
+    ];
+    if text.contains("://") || SCHEMES.iter().any(|s| text.starts_with(s)) {
+        text.to_string()
+    } else if text.contains('@') {
+        format!("mailto:{}", text)
+    } else if text.starts_with("ftp") {
+        format!("ftp://{}", text)
+    } else {
+        format!("http://{}", text)
+    }
+}
+
+/// turn a path matched by `FILE_PATTERN` into a link if it names an existing file
+pub fn resolve_file(
+    path: &str,
+    line: Option<&str>,
+    column: Option<&str>,
+    directory: Option<&Path>,
+) -> Option<Link> {
+    let path = if path == "~" {
+        glib::home_dir()
+    } else if let Some(rest) = path.strip_prefix("~/") {
+        glib::home_dir().join(rest)
+    } else {
+        PathBuf::from(path)
+    };
+    let path = if path.is_absolute() {
+        path
+    } else {
+        directory?.join(path)
+    };
+    // a trailing dot is more likely to end the sentence than the file name
+    let path = if path.exists() {
+        path
+    } else {
+        let trimmed = PathBuf::from(path.to_str()?.trim_end_matches('.'));
+        if !trimmed.exists() {
+            return None;
+        }
+        trimmed
+    };
+    Some(Link::File {
+        path,
+        line: line.and_then(|l| l.parse().ok()),
+        column: column.and_then(|c| c.parse().ok()),
+    })
+}
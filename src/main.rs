@@ -1,21 +1,53 @@
 mod config;
+mod daemon;
+mod hints;
 mod layer_console;
+mod link;
+mod schema;
+mod session;
+mod shell_integration;
+mod terminal;
+mod tmux;
 mod util;
 
 use gtk::gdk;
+use gtk::gio;
 use gtk::gio::ApplicationCommandLine;
 use gtk::gio::ApplicationFlags;
+use gtk::glib;
 use gtk::glib::OptionArg;
 use gtk::glib::OptionFlags;
 use gtk::prelude::*;
 use gtk::Application;
-use gtk4_layer_shell::LayerShell;
+use gtk4_layer_shell::{KeyboardMode, LayerShell};
+use layer_console::{DEFAULT_COLUMNS, DEFAULT_FONT, DEFAULT_ROWS};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
 
 pub const G_LOG_DOMAIN: &str = "layer-console";
+/// keys of the config file only read when a console starts
+const STARTUP_KEYS: [&str; 5] = [
+    "restore_session",
+    "restore_commands",
+    "tmux",
+    "tabs",
+    "strict",
+];
+/// changes of the config file are gathered for this long before reloading it
+const CONFIG_RELOAD_DELAY: Duration = Duration::from_millis(200);
 
-fn on_commandline(app: &Application, command_line: &ApplicationCommandLine) -> i32 {
-    let options = command_line.options_dict();
-    let position = if options.contains("top") {
+thread_local! {
+    /// config last applied to each console, by name
+    static APPLIED_CONFIGS: RefCell<HashMap<Option<String>, config::Config>> =
+        RefCell::default();
+    static CONFIG_MONITORS: RefCell<HashMap<std::path::PathBuf, Vec<gio::FileMonitor>>> =
+        RefCell::default();
+}
+
+fn option_position(options: &glib::VariantDict) -> Option<config::Position> {
+    if options.contains("top") {
         Some(config::Position::Top)
     } else if options.contains("bottom") {
         Some(config::Position::Bottom)
@@ -25,57 +57,152 @@ fn on_commandline(app: &Application, command_line: &ApplicationCommandLine) -> i
         Some(config::Position::Right)
     } else {
         None
-    };
-    let keyboard_mode = if options.contains("exclusive") {
+    }
+}
+
+fn option_keyboard_mode(options: &glib::VariantDict) -> Option<config::KeyboardMode> {
+    if options.contains("exclusive") {
         Some(config::KeyboardMode::Exclusive)
     } else if options.contains("on-demand") {
         Some(config::KeyboardMode::OnDemand)
     } else {
         None
-    };
-
-    let rows = options.lookup::<i32>("rows").unwrap().map(|i| i.into());
-    let columns = options.lookup::<i32>("columns").unwrap().map(|i| i.into());
+    }
+}
 
-    if let Some(win) = app.active_window() {
-        if let Ok(win) = win.clone().downcast::<layer_console::LayerConsoleWindow>() {
-            if let Some(font) = options.lookup::<String>("font").unwrap() {
-                win.set_font(&font);
-            }
-            if let Some(position) = position {
-                win.set_position(position.as_position());
+/// handle the options which don't need the running instance, -1 to go on
+fn on_local_options(options: &glib::VariantDict) -> i32 {
+    let config_path = options.lookup::<std::path::PathBuf>("config").unwrap();
+    if options.contains("check-config") {
+        let path = config_path
+            .clone()
+            .unwrap_or_else(config::default_config_path);
+        let problems = config::check_config(config_path);
+        if problems.is_empty() {
+            println!("{}: ok", path.display());
+            return 0;
+        }
+        for problem in problems {
+            eprintln!("{}: {}", path.display(), problem);
+        }
+        return 1;
+    }
+    if options.contains("print-config-schema") {
+        print!("{}", schema::config_schema());
+        return 0;
+    }
+    if options.contains("print-config") {
+        let profile = options.lookup::<String>("profile").unwrap();
+        let mut config = match load_console_config(config_path, profile.as_deref()) {
+            Ok((config, _)) => config,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
             }
-            if let Some(keyboard_mode) = keyboard_mode {
-                win.set_keyboard_mode(keyboard_mode.as_keyboard_mode());
+        };
+        // the options given along override the file, as when starting a console
+        config.profiles.clear();
+        config.position = option_position(options).or(config.position);
+        config.keyboard_mode = option_keyboard_mode(options).or(config.keyboard_mode);
+        config.font = options.lookup("font").unwrap().or(config.font);
+        config.rows = options
+            .lookup::<i32>("rows")
+            .unwrap()
+            .map(i64::from)
+            .or(config.rows);
+        config.columns = options
+            .lookup::<i32>("columns")
+            .unwrap()
+            .map(i64::from)
+            .or(config.columns);
+        config.working_directory = options
+            .lookup("working-directory")
+            .unwrap()
+            .or(config.working_directory);
+        config.tmux = options.lookup("tmux").unwrap().or(config.tmux);
+        return match toml::to_string(&config.with_defaults()) {
+            Ok(text) => {
+                print!("{}", text);
+                0
             }
-            match (columns, rows) {
-                (None, None) => (),
-                _ => win.set_terminal_size(columns, rows),
+            Err(e) => {
+                eprintln!("can't print the config: {}", e);
+                1
             }
-            win.toggle();
-        } else {
-            panic!("failed to downcast {:?}", win);
+        };
+    }
+    -1
+}
+
+fn on_commandline(app: &Application, command_line: &ApplicationCommandLine) -> i32 {
+    let options = command_line.options_dict();
+    let position = option_position(&options);
+    let keyboard_mode = option_keyboard_mode(&options);
+
+    let rows = options.lookup::<i32>("rows").unwrap().map(|i| i.into());
+    let columns = options.lookup::<i32>("columns").unwrap().map(|i| i.into());
+    let profile = options.lookup::<String>("profile").unwrap();
+    // consoles of a profile are named after it unless told otherwise
+    let name = options
+        .lookup::<String>("name")
+        .unwrap()
+        .or_else(|| profile.clone());
+    let config_path = options.lookup::<std::path::PathBuf>("config").unwrap();
+
+    if app.windows().is_empty() && !options.contains("status") {
+        restore_session(app, config_path.clone());
+    }
+
+    if let Some(win) = find_console(app, name.as_deref()) {
+        if options.contains("status") {
+            command_line.print_literal(&status(&win));
+            return 0;
+        }
+        if let Some(font) = options.lookup::<String>("font").unwrap() {
+            win.set_font(&font);
         }
+        if let Some(position) = position {
+            win.set_position(position.as_position());
+        }
+        if let Some(keyboard_mode) = keyboard_mode {
+            win.set_keyboard_mode(keyboard_mode.as_keyboard_mode());
+        }
+        match (columns, rows) {
+            (None, None) => (),
+            _ => win.set_terminal_size(columns, rows),
+        }
+        win.toggle();
         return 0;
     }
-    let win = layer_console::LayerConsoleWindow::new(app);
+    if options.contains("status") {
+        command_line.printerr_literal("layer-console is not running\n");
+        return 1;
+    }
 
-    let config = config::load_config(options.lookup::<std::path::PathBuf>("config").unwrap());
+    let (config, errors) = match load_console_config(config_path.clone(), profile.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            command_line.printerr_literal(&format!("{}\n", e));
+            return 1;
+        }
+    };
+    if !errors.is_empty() && (options.contains("strict") || config.strict.unwrap_or(false)) {
+        command_line.printerr_literal(&format!(
+            "not starting with errors in the config file:\n{}\n",
+            errors.join("\n")
+        ));
+        return 1;
+    }
+    let win = create_console(app, name, profile, config_path, &config);
+    win.show_config_errors(&errors);
 
-    let working_directory = options
-        .lookup::<String>("working-directory")
-        .unwrap()
-        .or(config.working_directory);
-    let rows = rows.or(config.rows);
-    let columns = columns.or(config.columns);
-    let font = options.lookup::<String>("font").unwrap().or(config.font);
-    let position = position.or(config.position);
-    let keyboard_mode = keyboard_mode.or(config.keyboard_mode);
-    let shell = config.shell.unwrap_or_else(|| util::get_user_shell());
-
-    win.set_working_directory(working_directory);
-    win.set_terminal_size(columns, rows);
-    if let Some(font) = font {
+    if let Some(working_directory) = options.lookup::<String>("working-directory").unwrap() {
+        win.set_working_directory(Some(working_directory));
+    }
+    if columns.is_some() || rows.is_some() {
+        win.set_terminal_size(columns, rows);
+    }
+    if let Some(font) = options.lookup::<String>("font").unwrap() {
         win.set_font(&font);
     }
     if let Some(position) = position {
@@ -85,6 +212,10 @@ fn on_commandline(app: &Application, command_line: &ApplicationCommandLine) -> i
         win.set_keyboard_mode(keyboard_mode.as_keyboard_mode());
     }
 
+    let tmux = options
+        .lookup::<String>("tmux")
+        .unwrap()
+        .or_else(|| config.tmux.clone());
     if options.contains("command") {
         let mut args = command_line
             .arguments()
@@ -96,14 +227,616 @@ fn on_commandline(app: &Application, command_line: &ApplicationCommandLine) -> i
             // remove first occurence of "--"
             args.remove(index);
         }
-        win.spawn(&args.iter().map(String::as_str).collect::<Vec<_>>());
+        win.add_tab(
+            &args.iter().map(String::as_str).collect::<Vec<_>>(),
+            None,
+            None,
+            &[],
+            false,
+        );
+    } else if let Some(session) = tmux {
+        if let Err(e) = win.attach_tmux(&session) {
+            glib::g_warning!(
+                G_LOG_DOMAIN,
+                "can't attach tmux session `{}`: {}",
+                session,
+                e
+            );
+            open_tabs(&win, &config);
+        }
     } else {
-        win.spawn(&[&shell]);
+        open_tabs(&win, &config);
     }
     win.present();
     return 0;
 }
 
+/// open the tabs of the config file, or a single shell when there is none
+fn open_tabs(win: &layer_console::LayerConsoleWindow, config: &config::Config) {
+    let shell = win.shell_command();
+    for tab in &config.tabs {
+        let (args, login_shell) = match tab.args() {
+            Some(Ok(args)) if !args.is_empty() => (args, false),
+            Some(Ok(_)) | None => (shell.clone(), win.login_shell()),
+            Some(Err(e)) => {
+                glib::g_warning!(
+                    G_LOG_DOMAIN,
+                    "invalid tab command `{}`: {}",
+                    tab.command.as_deref().unwrap_or_default(),
+                    e
+                );
+                continue;
+            }
+        };
+        let env = tab.env();
+        win.add_tab(
+            &args.iter().map(String::as_str).collect::<Vec<_>>(),
+            tab.working_directory.as_deref(),
+            tab.title.as_deref(),
+            &env.iter().map(String::as_str).collect::<Vec<_>>(),
+            login_shell,
+        );
+    }
+    if win.terminals().is_empty() {
+        win.add_tab(
+            &shell.iter().map(String::as_str).collect::<Vec<_>>(),
+            None,
+            None,
+            &[],
+            win.login_shell(),
+        );
+    }
+    win.set_current_tab(0);
+}
+
+/// the config file with the keys of `profile` applied, with its errors
+fn load_console_config(
+    config_path: Option<std::path::PathBuf>,
+    profile: Option<&str>,
+) -> Result<(config::Config, Vec<String>), String> {
+    let (config, errors) = config::load_config(config_path);
+    Ok((with_profile(config, profile)?, errors))
+}
+
+fn with_profile(config: config::Config, profile: Option<&str>) -> Result<config::Config, String> {
+    match profile {
+        Some(profile) => config
+            .with_profile(profile)
+            .ok_or_else(|| format!("unknown profile `{}`", profile)),
+        None => Ok(config),
+    }
+}
+
+/// create a console configured from `config`, without starting anything in it
+fn create_console(
+    app: &Application,
+    name: Option<String>,
+    profile: Option<String>,
+    config_path: Option<std::path::PathBuf>,
+    config: &config::Config,
+) -> layer_console::LayerConsoleWindow {
+    let win = layer_console::LayerConsoleWindow::new(app);
+    win.set_console_name(name);
+    win.set_profile(profile);
+    win.set_config_file(Some(
+        config_path.unwrap_or_else(config::default_config_path),
+    ));
+
+    win.set_shell(vec![util::get_user_shell()]);
+    apply_config(&win, &config::Config::default(), config);
+    APPLIED_CONFIGS.with_borrow_mut(|configs| {
+        configs.insert(win.console_name(), config.clone());
+    });
+    if let Some(path) = win.config_file() {
+        watch_config(app, path);
+    }
+
+    win.connect_state_changed(glib::clone!(
+        #[weak]
+        app,
+        move |_win| session::schedule_save(&app)
+    ));
+    win
+}
+
+/// apply the keys of `config` which differ from `previous`, the removed
+/// ones going back to their default, returning the keys which changed
+fn apply_config(
+    win: &layer_console::LayerConsoleWindow,
+    previous: &config::Config,
+    config: &config::Config,
+) -> Vec<&'static str> {
+    let config::Config {
+        shell,
+        login_shell,
+        working_directory,
+        rows,
+        columns,
+        font,
+        position,
+        keyboard_mode,
+        copy_on_select,
+        middle_click_paste,
+        confirm_paste,
+        matchers,
+        opener,
+        url_handlers,
+        hide_on_open,
+        link_modifier,
+        editor,
+        cursor_shape,
+        cursor_blink,
+        cursor_color,
+        cursor_foreground,
+        bell,
+        notify_when_hidden,
+        notify_min_duration,
+        // only read when the console starts
+        restore_session: _,
+        restore_commands: _,
+        save_scrollback,
+        scrollback_save_limit,
+        daemon,
+        tmux: _,
+        strict: _,
+        tabs: _,
+        // merged by config::load
+        include: _,
+        profiles: _,
+    } = config;
+    if *shell != previous.shell {
+        win.set_shell(
+            shell
+                .as_ref()
+                .map_or_else(|| vec![util::get_user_shell()], config::Shell::args),
+        );
+    }
+    if *login_shell != previous.login_shell {
+        win.set_login_shell(login_shell.unwrap_or(false));
+    }
+    if *working_directory != previous.working_directory {
+        win.set_working_directory(working_directory.clone());
+    }
+    if *rows != previous.rows {
+        win.set_terminal_size(None, Some(rows.unwrap_or(DEFAULT_ROWS)));
+    }
+    if *columns != previous.columns {
+        win.set_terminal_size(Some(columns.unwrap_or(DEFAULT_COLUMNS)), None);
+    }
+    if *font != previous.font {
+        win.set_font(font.as_deref().unwrap_or(DEFAULT_FONT));
+    }
+    if *position != previous.position {
+        win.set_position(
+            position
+                .as_ref()
+                .map_or(layer_console::Position::Top, config::Position::as_position),
+        );
+    }
+    if *keyboard_mode != previous.keyboard_mode {
+        win.set_keyboard_mode(keyboard_mode.as_ref().map_or(
+            KeyboardMode::OnDemand,
+            config::KeyboardMode::as_keyboard_mode,
+        ));
+    }
+    if *copy_on_select != previous.copy_on_select {
+        win.set_copy_on_select(copy_on_select.unwrap_or(false));
+    }
+    if *middle_click_paste != previous.middle_click_paste {
+        win.set_middle_click_paste(middle_click_paste.unwrap_or(true));
+    }
+    if *confirm_paste != previous.confirm_paste {
+        win.set_confirm_paste(confirm_paste.unwrap_or(true));
+    }
+    if *matchers != previous.matchers {
+        win.clear_matchers();
+        for matcher in matchers {
+            let Some(template) = matcher.as_template() else {
+                glib::g_warning!(
+                    G_LOG_DOMAIN,
+                    "matcher `{}` needs exactly one of `url` or `command`",
+                    matcher.regex
+                );
+                continue;
+            };
+            if let Err(e) = win.add_matcher(&matcher.regex, template) {
+                glib::g_warning!(
+                    G_LOG_DOMAIN,
+                    "invalid matcher regex `{}`: {}",
+                    matcher.regex,
+                    e
+                );
+            }
+        }
+    }
+    if *opener != previous.opener {
+        win.set_opener(opener.clone());
+    }
+    if *url_handlers != previous.url_handlers {
+        win.reset_url_handlers();
+        for (scheme, command) in url_handlers {
+            win.set_url_handler(scheme, command);
+        }
+    }
+    if *hide_on_open != previous.hide_on_open {
+        win.set_hide_on_open(hide_on_open.unwrap_or(false));
+    }
+    if *link_modifier != previous.link_modifier {
+        win.set_link_modifier(
+            link_modifier
+                .as_ref()
+                .map_or(gdk::ModifierType::CONTROL_MASK, |modifier| {
+                    modifier.as_modifier_type()
+                }),
+        );
+    }
+    if *editor != previous.editor {
+        win.set_editor(editor.clone());
+    }
+    if *cursor_shape != previous.cursor_shape {
+        win.set_cursor_shape(cursor_shape.as_ref().map_or(
+            vte4::CursorShape::Block,
+            config::CursorShape::as_cursor_shape,
+        ));
+    }
+    if *cursor_blink != previous.cursor_blink {
+        win.set_cursor_blink_mode(
+            cursor_blink
+                .as_ref()
+                .map_or(vte4::CursorBlinkMode::System, |blink| {
+                    blink.as_cursor_blink_mode()
+                }),
+        );
+    }
+    if *cursor_color != previous.cursor_color || *cursor_foreground != previous.cursor_foreground {
+        let cursor_color = cursor_color.as_deref().and_then(parse_color);
+        let cursor_foreground = cursor_foreground.as_deref().and_then(parse_color);
+        win.set_cursor_colors(cursor_color.as_ref(), cursor_foreground.as_ref());
+    }
+    if *bell != previous.bell {
+        win.set_bell(
+            bell.as_ref()
+                .map_or(layer_console::Bell::Audible, config::Bell::as_bell),
+        );
+    }
+    if *notify_when_hidden != previous.notify_when_hidden {
+        win.set_notify_when_hidden(notify_when_hidden.unwrap_or(false));
+    }
+    if *notify_min_duration != previous.notify_min_duration {
+        win.set_notify_min_duration(
+            notify_min_duration.unwrap_or(layer_console::DEFAULT_NOTIFY_MIN_DURATION),
+        );
+    }
+    if *save_scrollback != previous.save_scrollback {
+        win.set_save_scrollback(save_scrollback.unwrap_or(false));
+    }
+    if *scrollback_save_limit != previous.scrollback_save_limit {
+        win.set_scrollback_save_limit(
+            scrollback_save_limit.unwrap_or(layer_console::DEFAULT_SCROLLBACK_SAVE_LIMIT),
+        );
+    }
+    if *daemon != previous.daemon {
+        win.set_daemon(daemon.unwrap_or(false));
+    }
+    previous.changed_keys(config)
+}
+
+/// follow the changes of the config file at `path`, once per file
+fn watch_config(app: &Application, path: std::path::PathBuf) {
+    if CONFIG_MONITORS.with_borrow(|monitors| monitors.contains_key(&path)) {
+        return;
+    }
+    update_config_monitors(app, &path);
+}
+
+/// watch the files the config file at `path` is made of, and its drop-in
+/// directory, in place of the ones it was made of until now
+fn update_config_monitors(app: &Application, path: &std::path::Path) {
+    let mut files = config::config_files(path);
+    files.push(config::drop_in_dir(path));
+    // editors write the file in several steps, reload once they are done
+    let pending = Rc::new(RefCell::new(None::<glib::SourceId>));
+    let monitors = files
+        .iter()
+        .filter_map(|file| {
+            match gio::File::for_path(file)
+                .monitor(gio::FileMonitorFlags::WATCH_MOVES, gio::Cancellable::NONE)
+            {
+                Ok(monitor) => Some(monitor),
+                Err(e) => {
+                    glib::g_warning!(G_LOG_DOMAIN, "can't watch {}: {}", file.display(), e);
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    let path = path.to_path_buf();
+    for monitor in &monitors {
+        monitor.connect_changed(glib::clone!(
+            #[weak]
+            app,
+            #[strong]
+            path,
+            #[strong]
+            pending,
+            move |_monitor, _file, _other, event| {
+                if matches!(
+                    event,
+                    gio::FileMonitorEvent::Changed | gio::FileMonitorEvent::AttributeChanged
+                ) {
+                    return;
+                }
+                if let Some(source) = pending.take() {
+                    source.remove();
+                }
+                pending.replace(Some(glib::timeout_add_local_once(
+                    CONFIG_RELOAD_DELAY,
+                    glib::clone!(
+                        #[weak]
+                        app,
+                        #[strong]
+                        path,
+                        #[strong]
+                        pending,
+                        move || {
+                            pending.take();
+                            reload_config(&app, &path);
+                        }
+                    ),
+                )));
+            }
+        ));
+    }
+    CONFIG_MONITORS.with_borrow_mut(|all| all.insert(path, monitors));
+}
+
+/// apply the config file at `path` again to the consoles using it
+fn reload_config(app: &Application, path: &std::path::Path) {
+    let windows = app
+        .windows()
+        .into_iter()
+        .filter_map(|win| win.downcast::<layer_console::LayerConsoleWindow>().ok())
+        .filter(|win| win.config_file().as_deref() == Some(path));
+    for win in windows {
+        let name = win.console_name();
+        let label = name.as_deref().unwrap_or("default");
+        let loaded = config::read_config(Some(path.to_path_buf())).and_then(|(config, errors)| {
+            Ok((with_profile(config, win.profile().as_deref())?, errors))
+        });
+        let config = match loaded {
+            Ok((config, errors)) => {
+                for error in &errors {
+                    glib::g_warning!(G_LOG_DOMAIN, "config file: {}", error);
+                }
+                win.show_config_errors(&errors);
+                config
+            }
+            Err(e) => {
+                glib::g_warning!(
+                    G_LOG_DOMAIN,
+                    "keeping the config of console {}: {}",
+                    label,
+                    e
+                );
+                win.show_config_errors(&[e]);
+                continue;
+            }
+        };
+        let previous = APPLIED_CONFIGS
+            .with_borrow(|configs| configs.get(&name).cloned())
+            .unwrap_or_default();
+        let changed = apply_config(&win, &previous, &config);
+        APPLIED_CONFIGS.with_borrow_mut(|configs| configs.insert(name.clone(), config));
+        if changed.is_empty() {
+            continue;
+        }
+        let (on_start, applied): (Vec<_>, Vec<_>) = changed
+            .into_iter()
+            .partition(|key| STARTUP_KEYS.contains(key));
+        if !applied.is_empty() {
+            glib::g_message!(
+                G_LOG_DOMAIN,
+                "console {}: applied {}",
+                label,
+                applied.join(", ")
+            );
+        }
+        if !on_start.is_empty() {
+            glib::g_message!(
+                G_LOG_DOMAIN,
+                "console {}: {} changed, restart to apply",
+                label,
+                on_start.join(", ")
+            );
+        }
+    }
+    // the files included may have changed
+    update_config_monitors(app, path);
+}
+
+/// bring back the consoles of the last session, hidden until toggled.
+/// with the daemon they are needed to get back the processes it runs.
+fn restore_session(app: &Application, config_path: Option<std::path::PathBuf>) {
+    let (config, errors) = config::load_config(config_path.clone());
+    let daemon = config.daemon.unwrap_or(false);
+    if !config.restore_session.unwrap_or(false) && !daemon {
+        return;
+    }
+    // the console refuses to start, telling why
+    if !errors.is_empty() && config.strict.unwrap_or(false) {
+        return;
+    }
+    session::enable();
+    // sessions of the daemon attached by the restored tabs
+    let mut restored = Vec::new();
+    for state in session::load().consoles {
+        let (config, errors) =
+            match load_console_config(state.config_file.clone(), state.profile.as_deref()) {
+                Ok(config) => config,
+                Err(e) => {
+                    glib::g_warning!(G_LOG_DOMAIN, "can't restore console: {}", e);
+                    continue;
+                }
+            };
+        if !errors.is_empty() && config.strict.unwrap_or(false) {
+            glib::g_warning!(
+                G_LOG_DOMAIN,
+                "not restoring console with errors in its config file"
+            );
+            continue;
+        }
+        let win = create_console(app, state.name, state.profile, state.config_file, &config);
+        win.show_config_errors(&errors);
+        if let Some(position) = layer_console::Position::from_name(&state.position) {
+            win.set_position(position);
+        }
+        win.set_terminal_size(Some(state.columns), Some(state.rows));
+        let shell = win.shell_command();
+        for tab in &state.tabs {
+            let (args, login_shell) =
+                if config.restore_commands.unwrap_or(false) && !tab.command.is_empty() {
+                    (tab.command.clone(), tab.login_shell)
+                } else {
+                    (shell.clone(), win.login_shell())
+                };
+            let terminal = match tab.daemon_id.filter(|_| win.daemon()) {
+                Some(id) => {
+                    restored.push(id);
+                    let directory = tab.working_directory.clone();
+                    let env = tab.env.clone();
+                    win.attach_tab(id, tab.title.as_deref(), move |terminal, e| {
+                        glib::g_warning!(G_LOG_DOMAIN, "can't attach session {}: {}", id, e);
+                        terminal.spawn_in_daemon(
+                            directory.as_deref(),
+                            &args.iter().map(String::as_str).collect::<Vec<_>>(),
+                            &env.iter().map(String::as_str).collect::<Vec<_>>(),
+                            login_shell,
+                        );
+                    })
+                }
+                None => win.add_tab(
+                    &args.iter().map(String::as_str).collect::<Vec<_>>(),
+                    tab.working_directory.as_deref(),
+                    tab.title.as_deref(),
+                    &tab.env.iter().map(String::as_str).collect::<Vec<_>>(),
+                    login_shell,
+                ),
+            };
+            if let Some(title) = &tab.terminal_title {
+                terminal.restore_title(title);
+            }
+            if let Some(path) = &tab.scrollback {
+                // kept until the next quit saves it again, in case the console dies first
+                let restored = win.save_scrollback()
+                    && match session::load_scrollback(path) {
+                        Ok((text, saved)) => {
+                            terminal.replay_scrollback(&text, saved.as_ref());
+                            true
+                        }
+                        Err(e) => {
+                            glib::g_warning!(G_LOG_DOMAIN, "can't restore scrollback: {}", e);
+                            false
+                        }
+                    };
+                if restored {
+                    terminal.set_restored_scrollback(Some(path.clone()));
+                } else {
+                    session::remove_scrollback(path);
+                }
+            }
+        }
+        if let Some(session) = &state.tmux {
+            if let Err(e) = win.attach_tmux(session) {
+                glib::g_warning!(
+                    G_LOG_DOMAIN,
+                    "can't attach tmux session `{}`: {}",
+                    session,
+                    e
+                );
+            }
+        }
+        if state.tabs.is_empty() && state.tmux.is_none() {
+            open_tabs(&win, &config);
+        }
+        win.set_current_tab(state.current_tab);
+    }
+    if daemon {
+        attach_left_sessions(app, config_path, &config, restored);
+    }
+}
+
+/// give the processes of the daemon which are not in the session, as when
+/// the console crashed before saving it, to the default console
+fn attach_left_sessions(
+    app: &Application,
+    config_path: Option<std::path::PathBuf>,
+    config: &config::Config,
+    restored: Vec<u64>,
+) {
+    let config = config.clone();
+    daemon::detached_sessions(glib::clone!(
+        #[weak]
+        app,
+        move |ids| {
+            let ids = ids
+                .into_iter()
+                .filter(|id| !restored.contains(id))
+                .collect::<Vec<_>>();
+            if ids.is_empty() {
+                return;
+            }
+            let win = find_console(&app, None)
+                .unwrap_or_else(|| create_console(&app, None, None, config_path, &config));
+            for id in ids {
+                win.attach_tab(id, None, move |terminal, e| {
+                    glib::g_warning!(G_LOG_DOMAIN, "can't attach session {}: {}", id, e);
+                    if let Some(win) = terminal
+                        .root()
+                        .and_downcast::<layer_console::LayerConsoleWindow>()
+                    {
+                        win.close_tab(terminal);
+                    }
+                });
+            }
+        }
+    ));
+}
+
+/// console called `name`, the default one for `None`
+fn find_console(
+    app: &Application,
+    name: Option<&str>,
+) -> Option<layer_console::LayerConsoleWindow> {
+    app.windows()
+        .into_iter()
+        .filter_map(|win| win.downcast::<layer_console::LayerConsoleWindow>().ok())
+        .find(|win| win.console_name().as_deref() == name)
+}
+
+fn parse_color(color: &str) -> Option<gdk::RGBA> {
+    match gdk::RGBA::parse(color) {
+        Ok(rgba) => Some(rgba),
+        Err(e) => {
+            glib::g_warning!(G_LOG_DOMAIN, "invalid color `{}`: {}", color, e);
+            None
+        }
+    }
+}
+
+fn status(win: &layer_console::LayerConsoleWindow) -> String {
+    format!(
+        "name: {}\nprofile: {}\nvisible: {}\nurgent: {}\nposition: {}\ncurrent-directory: {}\n",
+        win.console_name().unwrap_or_default(),
+        win.profile().unwrap_or_default(),
+        win.is_visible(),
+        win.urgent(),
+        win.position().name(),
+        win.current_directory()
+            .or_else(|| win.working_directory())
+            .unwrap_or_default(),
+    )
+}
+
 fn add_main_options(app: &Application) {
     app.add_main_option(
         "command",
@@ -193,6 +926,46 @@ fn add_main_options(app: &Application) {
         "Set keyboard mode on-demand (default)",
         None,
     );
+    app.add_main_option(
+        "status",
+        b'\0'.into(),
+        OptionFlags::NONE,
+        OptionArg::None,
+        "Print the state of the running console",
+        None,
+    );
+    app.add_main_option(
+        "name",
+        b'n'.into(),
+        OptionFlags::NONE,
+        OptionArg::String,
+        "Toggle the console with this name, starting it if needed",
+        Some("NAME"),
+    );
+    app.add_main_option(
+        "profile",
+        b'p'.into(),
+        OptionFlags::NONE,
+        OptionArg::String,
+        "Run a profile from the config file, in a console named after it",
+        Some("NAME"),
+    );
+    app.add_main_option(
+        "tmux",
+        b'\0'.into(),
+        OptionFlags::NONE,
+        OptionArg::String,
+        "Show the windows of a tmux session as tabs, creating it if needed",
+        Some("SESSION"),
+    );
+    app.add_main_option(
+        "daemon",
+        b'\0'.into(),
+        OptionFlags::NONE,
+        OptionArg::None,
+        "Run the daemon keeping the processes of the consoles, as the first argument",
+        None,
+    );
     app.add_main_option(
         "config",
         b'\0'.into(),
@@ -201,9 +974,45 @@ fn add_main_options(app: &Application) {
         "config file path",
         Some("CONFIG"),
     );
+    app.add_main_option(
+        "strict",
+        b'\0'.into(),
+        OptionFlags::NONE,
+        OptionArg::None,
+        "Refuse to start when the config file has errors",
+        None,
+    );
+    app.add_main_option(
+        "check-config",
+        b'\0'.into(),
+        OptionFlags::NONE,
+        OptionArg::None,
+        "Report the problems of the config file and exit, failing if there are any",
+        None,
+    );
+    app.add_main_option(
+        "print-config",
+        b'\0'.into(),
+        OptionFlags::NONE,
+        OptionArg::None,
+        "Print the configuration the console would run with and exit",
+        None,
+    );
+    app.add_main_option(
+        "print-config-schema",
+        b'\0'.into(),
+        OptionFlags::NONE,
+        OptionArg::None,
+        "Print the JSON Schema of the config file and exit",
+        None,
+    );
 }
 
 fn main() {
+    // the daemon has to run without the display the application needs
+    if std::env::args().nth(1).as_deref() == Some("--daemon") {
+        std::process::exit(daemon::run());
+    }
     let mut application_id = "org.u7fa9.layer-console";
     if cfg!(debug_assertions) {
         // change application_id if it is not a release build
@@ -214,6 +1023,7 @@ fn main() {
         .flags(ApplicationFlags::CAN_OVERRIDE_APP_ID | ApplicationFlags::HANDLES_COMMAND_LINE)
         .build();
     add_main_options(&app);
+    app.connect_handle_local_options(|_app, options| on_local_options(options));
     app.connect_startup(|app| {
         let display = gdk::Display::default().expect("can't get display");
         let provider = gtk::CssProvider::new();
@@ -242,6 +1052,24 @@ fn main() {
                 border-width: 1px 0px 1px 1px;
                 padding-right: 0.5em;
             }
+            vte-terminal.bell, window.urgent vte-terminal {
+                border-color: #f4bf75;
+            }
+            .hint {
+                background-color: #f4bf75;
+                color: #181818;
+                font-family: monospace;
+                font-weight: bold;
+                padding: 0 2px;
+            }
+            .paste-confirmation {
+                background-color: #303030;
+                padding: 0.5em;
+            }
+            .config-errors {
+                background-color: #5f1e1e;
+                padding: 0.5em;
+            }
         "#,
         );
         gtk::style_context_add_provider_for_display(
@@ -249,12 +1077,69 @@ fn main() {
             &provider,
             gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
         );
+        // the consoles paste the selection on middle click themselves, and
+        // VTE must not when they leave the click to it (middle_click_paste)
+        gtk::Settings::for_display(&display).set_gtk_enable_primary_paste(false);
+
+        // target of the notifications sent by the consoles, with the
+        // name of the console or an empty string for the default one
+        let action = gio::SimpleAction::new("show-console", Some(glib::VariantTy::STRING));
+        action.connect_activate(glib::clone!(
+            #[weak]
+            app,
+            move |_action, parameter| {
+                let name = parameter
+                    .and_then(|p| p.get::<String>())
+                    .filter(|p| !p.is_empty());
+                if let Some(win) = find_console(&app, name.as_deref()) {
+                    win.present();
+                }
+            }
+        ));
+        app.add_action(&action);
 
         app.set_accels_for_action("win.copy", &["<Shift><Primary>c"]);
         app.set_accels_for_action("win.paste", &["<Shift><Primary>v"]);
+        app.set_accels_for_action("win.paste-selection", &["<Shift>Insert"]);
+        app.set_accels_for_action("win.search", &["<Shift><Primary>f"]);
+        app.set_accels_for_action("win.hints::open", &["<Shift><Primary>e"]);
+        app.set_accels_for_action("win.hints::copy", &["<Shift><Primary>y"]);
+        app.set_accels_for_action("win.previous-prompt", &["<Shift><Primary>z"]);
+        app.set_accels_for_action("win.next-prompt", &["<Shift><Primary>x"]);
+        app.set_accels_for_action("win.copy-output", &["<Shift><Primary>g"]);
+        app.set_accels_for_action("win.select-output", &["<Shift><Primary>o"]);
+        app.set_accels_for_action("win.new-tab", &["<Shift><Primary>t"]);
+        app.set_accels_for_action("win.close-tab", &["<Shift><Primary>w"]);
+        app.set_accels_for_action("win.next-tab", &["<Primary>Page_Down"]);
+        app.set_accels_for_action("win.previous-tab", &["<Primary>Page_Up"]);
         app.set_accels_for_action("win.fullscreen", &["F11"]);
+        app.set_accels_for_action(
+            "win.zoom-in",
+            &["<Primary>plus", "<Primary>equal", "<Primary>KP_Add"],
+        );
+        app.set_accels_for_action("win.zoom-out", &["<Primary>minus", "<Primary>KP_Subtract"]);
+        app.set_accels_for_action("win.zoom-reset", &["<Primary>0", "<Primary>KP_0"]);
     });
     app.connect_command_line(on_commandline);
+    app.connect_window_removed(|app, _win| session::schedule_save(app));
+    app.connect_shutdown(session::flush);
+    // the consoles are closed when quitting on a signal, keep them in the session
+    for signal in [nix::libc::SIGTERM, nix::libc::SIGHUP, nix::libc::SIGINT] {
+        glib::unix_signal_add_local(
+            signal,
+            glib::clone!(
+                #[weak]
+                app,
+                #[upgrade_or]
+                glib::ControlFlow::Break,
+                move || {
+                    session::freeze(&app);
+                    app.quit();
+                    glib::ControlFlow::Break
+                }
+            ),
+        );
+    }
 
     app.run_with_args(&std::env::args().collect::<Vec<_>>());
 }
@@ -0,0 +1,110 @@
+use schemars::generate::SchemaSettings;
+use schemars::transform::transform_subschemas;
+use schemars::Schema;
+use serde_json::Value;
+
+use crate::config::Config;
+
+/// the JSON Schema of the config file, giving editors like taplo or VS Code
+/// validation and completion. it is derived from `config::Config`.
+pub fn config_schema() -> String {
+    let mut settings = SchemaSettings::draft07();
+    // before the transforms of draft 7, which give the `$ref` no siblings
+    settings.transforms.insert(0, Box::new(remove_null));
+    let schema = settings.into_generator().into_root_schema_for::<Config>();
+    let mut json = serde_json::to_string_pretty(&schema).unwrap();
+    json.push('\n');
+    json
+}
+
+/// drop the null schemars allows for the `Option` keys, as TOML has none:
+/// the keys are left out instead
+fn remove_null(schema: &mut Schema) {
+    let is_null = |value: &Value| {
+        value.is_null() || value == "null" || value.get("type").is_some_and(|kind| kind == "null")
+    };
+    if let Some(object) = schema.as_object_mut() {
+        for key in ["type", "enum", "anyOf"] {
+            let Some(Value::Array(values)) = object.get_mut(key) else {
+                continue;
+            };
+            values.retain(|value| !is_null(value));
+            if key == "type" && values.len() == 1 {
+                let kind = values.remove(0);
+                object.insert(key.into(), kind);
+            }
+        }
+        // the schema of a `$ref` is left alone in `anyOf`
+        if let Some(Value::Array(any_of)) = object.get("anyOf") {
+            if let [Value::Object(only)] = any_of.as_slice() {
+                let only = only.clone();
+                object.remove("anyOf");
+                object.extend(only);
+            }
+        }
+    }
+    transform_subschemas(&mut remove_null, schema);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the keys of the config taking one of the values of an enum, with them
+    fn enum_keys() -> Vec<(String, Vec<String>)> {
+        let schema = serde_json::from_str::<Value>(&config_schema()).unwrap();
+        let properties = schema["properties"].as_object().unwrap();
+        properties
+            .iter()
+            .filter_map(|(key, property)| {
+                let reference = property["$ref"]
+                    .as_str()
+                    .or_else(|| property["allOf"][0]["$ref"].as_str())?;
+                let name = reference.strip_prefix("#/definitions/")?;
+                let values = schema["definitions"][name]["enum"].as_array()?;
+                let values = values
+                    .iter()
+                    .map(|value| value.as_str().unwrap().to_string())
+                    .collect();
+                Some((key.clone(), values))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn enum_values_are_accepted() {
+        let keys = enum_keys();
+        assert!(keys.iter().any(|(key, _)| key == "keyboard_mode"));
+        for (key, values) in keys {
+            for value in values {
+                let text = format!("{} = \"{}\"", key, value);
+                if let Err(e) = toml::from_str::<Config>(&text) {
+                    panic!("`{}` is refused: {}", text, e.message());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn enum_values_are_listed() {
+        for (key, values) in enum_keys() {
+            // serde tells the variants it expects instead
+            let text = format!("{} = \"?\"", key);
+            let error = toml::from_str::<Config>(&text).unwrap_err();
+            let expected = error.message().split_once("expected").unwrap().1;
+            for variant in expected.split('`').skip(1).step_by(2) {
+                assert!(
+                    values.iter().any(|value| value == variant),
+                    "`{}` missing from the values of `{}`",
+                    variant,
+                    key
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn option_keys_are_not_null() {
+        assert!(!config_schema().contains("null"));
+    }
+}
@@ -0,0 +1,307 @@
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::layer_console::LayerConsoleWindow;
+use crate::terminal::LayerConsoleTerminal;
+use crate::G_LOG_DOMAIN;
+
+const STATE_DIR_NAME: &str = "layer-console";
+const SESSION_FILE_NAME: &str = "session.json";
+const SCROLLBACK_DIR_NAME: &str = "scrollback";
+/// changes are gathered for this long before the session is written
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// consoles running when the session was saved
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(default)]
+    pub consoles: Vec<ConsoleState>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConsoleState {
+    pub name: Option<String>,
+    pub profile: Option<String>,
+    pub config_file: Option<PathBuf>,
+    pub position: String,
+    pub columns: i64,
+    pub rows: i64,
+    #[serde(default)]
+    pub current_tab: u32,
+    #[serde(default)]
+    pub tabs: Vec<TabState>,
+    /// tmux session the console showed, which keeps its own windows
+    pub tmux: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TabState {
+    /// label given to the tab by the config file
+    pub title: Option<String>,
+    /// title set by the program running in the terminal
+    pub terminal_title: Option<String>,
+    pub working_directory: Option<String>,
+    /// arguments the tab was started with
+    #[serde(default)]
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub env: Vec<String>,
+    /// whether the command runs as a login shell
+    #[serde(default)]
+    pub login_shell: bool,
+    /// file keeping the scrollback, saved when quitting and kept by the
+    /// saves made meanwhile
+    pub scrollback: Option<PathBuf>,
+    /// session of the daemon the process runs in
+    pub daemon_id: Option<u64>,
+}
+
+/// terminals of the tabs kept in the session, leaving out the tmux panes
+fn saved_terminals(win: &LayerConsoleWindow) -> Vec<LayerConsoleTerminal> {
+    win.terminals()
+        .into_iter()
+        .filter(|terminal| !terminal.has_input_handler())
+        .collect()
+}
+
+impl ConsoleState {
+    fn from_window(win: &LayerConsoleWindow) -> ConsoleState {
+        let (columns, rows) = win.terminal_size();
+        let tabs = saved_terminals(win)
+            .iter()
+            .map(|terminal| TabState {
+                title: terminal.tab_title(),
+                terminal_title: terminal.title(),
+                working_directory: terminal
+                    .current_directory()
+                    .or_else(|| win.working_directory()),
+                command: terminal.command(),
+                env: terminal.env(),
+                login_shell: terminal.login_shell(),
+                scrollback: terminal.restored_scrollback(),
+                daemon_id: terminal.daemon_id(),
+            })
+            .collect();
+        ConsoleState {
+            name: win.console_name(),
+            profile: win.profile(),
+            config_file: win.config_file(),
+            position: win.position().name().to_string(),
+            columns,
+            rows,
+            current_tab: win.current_tab(),
+            tabs,
+            tmux: win.tmux_session(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Saver {
+    enabled: bool,
+    /// set when quitting, so that closing the consoles doesn't empty the session
+    frozen: bool,
+    pending: Option<glib::SourceId>,
+}
+
+thread_local! {
+    static SAVER: RefCell<Saver> = RefCell::default();
+}
+
+pub fn session_path() -> PathBuf {
+    let mut path = glib::user_state_dir();
+    path.push(STATE_DIR_NAME);
+    path.push(SESSION_FILE_NAME);
+    path
+}
+
+fn scrollback_dir() -> PathBuf {
+    let mut path = glib::user_state_dir();
+    path.push(STATE_DIR_NAME);
+    path.push(SCROLLBACK_DIR_NAME);
+    path
+}
+
+/// write the file only the user can read, through a temporary file
+/// so that a crash can't leave it half written
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    gio::File::for_path(path)
+        .replace_contents(
+            contents,
+            None,
+            false,
+            gio::FileCreateFlags::PRIVATE,
+            gio::Cancellable::NONE,
+        )
+        .map(|_| ())
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// the text of a saved scrollback with the time it was saved
+pub fn load_scrollback(path: &Path) -> std::io::Result<(String, Option<glib::DateTime>)> {
+    let text = std::fs::read_to_string(path)?;
+    let saved = std::fs::metadata(path)?
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .and_then(|age| glib::DateTime::from_unix_local(age.as_secs() as i64).ok());
+    Ok((text, saved))
+}
+
+pub fn remove_scrollback(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            glib::g_warning!(G_LOG_DOMAIN, "can't remove {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// save the scrollback of the consoles which keep it, filling `states` with their files
+///
+/// the files get new names, so that the session file still being there
+/// keeps its own until `remove_unused_scrollbacks`
+fn save_scrollbacks(windows: &[LayerConsoleWindow], states: &mut [ConsoleState]) {
+    let dir = scrollback_dir();
+    let stamp = glib::real_time();
+    for (i, (win, state)) in windows.iter().zip(states.iter_mut()).enumerate() {
+        if !win.save_scrollback() {
+            for tab in &mut state.tabs {
+                tab.scrollback = None;
+            }
+            continue;
+        }
+        let limit = win.scrollback_save_limit() as usize * 1024;
+        let terminals = saved_terminals(win);
+        for (j, (terminal, tab)) in terminals.iter().zip(state.tabs.iter_mut()).enumerate() {
+            let Some(text) = terminal.scrollback_text(limit) else {
+                tab.scrollback = None;
+                continue;
+            };
+            let path = dir.join(format!("{}-{}-{}.txt", i, j, stamp));
+            // the file restored, if any, is kept when this one can't be written
+            match write_private(&path, text.as_bytes()) {
+                Ok(()) => tab.scrollback = Some(path),
+                Err(e) => glib::g_warning!(G_LOG_DOMAIN, "can't save scrollback: {}", e),
+            }
+        }
+    }
+}
+
+/// remove the scrollback files `session` doesn't refer to, from consoles
+/// which are gone, don't save it anymore or saved it since
+fn remove_unused_scrollbacks(session: &Session) {
+    let Ok(entries) = std::fs::read_dir(scrollback_dir()) else {
+        return;
+    };
+    let used = session
+        .consoles
+        .iter()
+        .flat_map(|console| &console.tabs)
+        .filter_map(|tab| tab.scrollback.as_deref())
+        .collect::<Vec<_>>();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !used.contains(&path.as_path()) {
+            remove_scrollback(&path);
+        }
+    }
+}
+
+pub fn load() -> Session {
+    let path = session_path();
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Session::default(),
+        Err(e) => {
+            glib::g_warning!(G_LOG_DOMAIN, "can't read session file: {}", e);
+            return Session::default();
+        }
+    };
+    match serde_json::from_str(&text) {
+        Ok(session) => session,
+        Err(e) => {
+            glib::g_warning!(G_LOG_DOMAIN, "failed to parse session file: {}", e);
+            Session::default()
+        }
+    }
+}
+
+fn save(app: &gtk::Application, with_scrollback: bool) {
+    let windows = app
+        .windows()
+        .into_iter()
+        .filter_map(|win| win.downcast::<LayerConsoleWindow>().ok())
+        .collect::<Vec<_>>();
+    let mut consoles = windows
+        .iter()
+        .map(ConsoleState::from_window)
+        .collect::<Vec<_>>();
+    if with_scrollback {
+        save_scrollbacks(&windows, &mut consoles);
+    }
+    let session = Session { consoles };
+    let text = match serde_json::to_string_pretty(&session) {
+        Ok(text) => text,
+        Err(e) => {
+            glib::g_warning!(G_LOG_DOMAIN, "failed to serialize the session: {}", e);
+            return;
+        }
+    };
+    match write_private(&session_path(), text.as_bytes()) {
+        // the previous files are only dropped once the session refers to the new ones
+        Ok(()) if with_scrollback => remove_unused_scrollbacks(&session),
+        Ok(()) => (),
+        Err(e) => glib::g_warning!(G_LOG_DOMAIN, "can't write session file: {}", e),
+    }
+}
+
+/// start saving the session of `app` whenever it changes
+pub fn enable() {
+    SAVER.with_borrow_mut(|saver| saver.enabled = true);
+}
+
+/// save the session shortly, gathering the changes made meanwhile
+pub fn schedule_save(app: &gtk::Application) {
+    SAVER.with_borrow_mut(|saver| {
+        if !saver.enabled || saver.frozen || saver.pending.is_some() {
+            return;
+        }
+        let app = app.downgrade();
+        saver.pending = Some(glib::timeout_add_local_once(SAVE_DELAY, move || {
+            SAVER.with_borrow_mut(|saver| saver.pending = None);
+            if let Some(app) = app.upgrade() {
+                save(&app, false);
+            }
+        }));
+    });
+}
+
+/// save now if a change is waiting to be saved
+pub fn flush(app: &gtk::Application) {
+    let pending = SAVER.with_borrow_mut(|saver| saver.pending.take());
+    if let Some(source) = pending {
+        source.remove();
+        save(app, false);
+    }
+}
+
+/// save the session with the scrollbacks and keep it as it is from now on
+pub fn freeze(app: &gtk::Application) {
+    let enabled = SAVER.with_borrow_mut(|saver| {
+        if let Some(source) = saver.pending.take() {
+            source.remove();
+        }
+        saver.frozen = true;
+        saver.enabled
+    });
+    if enabled {
+        save(app, true);
+    }
+}
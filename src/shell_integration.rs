@@ -0,0 +1,45 @@
+/// host of the `file:` URIs the shell integration scripts send with OSC 6,
+/// next to their OSC 133 marks, as VTE ignores the latter but reports the
+/// former as the current file
+const HOST: &str = "layer-console";
+
+/// what the shell integration tells about the shell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Report {
+    /// the prompt has been printed, the command line starting at the cursor
+    Prompt {
+        /// commands run by the shell so far
+        commands: u64,
+        /// rows taken by the prompt
+        lines: u32,
+        /// exit status of the last command
+        status: Option<i32>,
+    },
+    /// the command entered starts running
+    Command {
+        /// commands run by the shell so far, this one included
+        commands: u64,
+    },
+}
+
+impl Report {
+    /// parse `file://layer-console/SEQUENCE/prompt/COMMANDS/LINES[/STATUS]`
+    /// or `file://layer-console/SEQUENCE/command/COMMANDS`, the sequence only
+    /// being there to tell apart the reports which are otherwise the same
+    pub fn parse(uri: &str) -> Option<Report> {
+        let path = uri.strip_prefix("file://")?.strip_prefix(HOST)?;
+        let mut fields = path.strip_prefix('/')?.split('/').skip(1);
+        let report = match fields.next()? {
+            "prompt" => Report::Prompt {
+                commands: fields.next()?.parse().ok()?,
+                lines: fields.next()?.parse::<u32>().ok()?.max(1),
+                status: fields.next().and_then(|status| status.parse().ok()),
+            },
+            "command" => Report::Command {
+                commands: fields.next()?.parse().ok()?,
+            },
+            _ => return None,
+        };
+        Some(report)
+    }
+}
@@ -0,0 +1,666 @@
+use gtk::gio;
+use gtk::glib;
+use gtk::glib::Unichar;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use vte4::prelude::*;
+
+use crate::daemon;
+use crate::G_LOG_DOMAIN;
+
+/// what a regex registered with `add_match` detects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    Url,
+    File,
+    /// user defined matcher, by its index in the console
+    Matcher(usize),
+}
+
+/// a command delimited by the shell integration marks, positions being
+/// absolute (row, column) of the terminal
+#[derive(Debug, Clone)]
+pub struct CommandRecord {
+    pub prompt: (i64, i64),
+    pub command_start: (i64, i64),
+    pub output_start: Option<(i64, i64)>,
+    pub output_end: Option<(i64, i64)>,
+    pub command: Option<String>,
+    pub started: Option<Instant>,
+    pub duration: Option<Duration>,
+    pub status: Option<i32>,
+}
+
+mod imp {
+    use super::{CommandRecord, MatchKind};
+    use crate::daemon;
+    use crate::shell_integration::Report;
+    use crate::util;
+    use crate::G_LOG_DOMAIN;
+    use glib::subclass::Signal;
+    use gtk::prelude::*;
+    use gtk::{gdk, gio, glib, graphene};
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::OnceLock;
+    use std::time::Instant;
+    use vte4::prelude::*;
+    use vte4::subclass::prelude::*;
+
+    type InputHandler = Box<dyn Fn(&[u8])>;
+
+    #[derive(Default)]
+    pub struct LayerConsoleTerminal {
+        pub(super) commands: RefCell<Vec<CommandRecord>>,
+        /// commands the shell reported having run
+        commands_run: Cell<u64>,
+        /// rows of the command output shown as selected, see `select_output`
+        pub(super) selected_output: Cell<Option<(i64, i64)>>,
+        /// arguments of the process running in the terminal
+        pub(super) command: RefCell<Vec<String>>,
+        /// variables added to the environment of the process
+        pub(super) env: RefCell<Vec<String>>,
+        /// whether the process runs as a login shell
+        pub(super) login_shell: Cell<bool>,
+        /// label of the tab, following the title of the terminal when unset
+        pub(super) tab_title: RefCell<Option<String>>,
+        pub(super) last_title: RefCell<Option<String>>,
+        pub(super) title_changed_at: Cell<Option<Instant>>,
+        pub(super) match_kinds: RefCell<HashMap<i32, MatchKind>>,
+        /// connection to the daemon when the process runs there
+        pub(super) daemon_client: RefCell<Option<daemon::Client>>,
+        /// receives the input instead of a pty, for processes running elsewhere
+        pub(super) input_handler: RefCell<Option<InputHandler>>,
+        /// file of the saved scrollback replayed in the terminal
+        pub(super) restored_scrollback: RefCell<Option<PathBuf>>,
+    }
+
+    impl LayerConsoleTerminal {
+        pub fn spawn(
+            &self,
+            directory: Option<&str>,
+            args: &[&str],
+            env: &[&str],
+            login_shell: bool,
+        ) {
+            self.command
+                .replace(args.iter().map(|arg| arg.to_string()).collect());
+            self.env
+                .replace(env.iter().map(|var| var.to_string()).collect());
+            self.login_shell.set(login_shell);
+            let (argv, flags) = util::spawn_args(args, login_shell);
+            self.obj().spawn_async(
+                vte4::PtyFlags::DEFAULT,
+                directory,
+                &argv.iter().map(String::as_str).collect::<Vec<_>>(),
+                env,
+                flags,
+                || {},
+                -1,
+                gio::Cancellable::NONE,
+                |result| {
+                    if let Err(e) = result {
+                        glib::g_warning!(G_LOG_DOMAIN, "failed to spawn: {}", e);
+                    }
+                },
+            );
+        }
+        /// follow a process of the daemon, `backlog` being what it printed meanwhile
+        pub fn open_daemon_session(&self, session: daemon::SessionInfo) {
+            self.command.replace(session.command);
+            self.env.replace(session.env);
+            self.login_shell.set(session.login_shell);
+            // VTE can't watch a process which isn't its child
+            session.client.connect_exited(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |status| this.obj().emit_by_name::<()>("child-exited", &[&status])
+            ));
+            self.daemon_client.replace(Some(session.client));
+            let obj = self.obj();
+            obj.feed(&session.backlog);
+            obj.set_pty(Some(&session.pty));
+            // programs redraw on SIGWINCH, which only a change of size sends
+            let (rows, columns) = (obj.row_count() as i32, obj.column_count() as i32);
+            if let Err(e) = session
+                .pty
+                .set_size(rows, columns + 1)
+                .and_then(|_| session.pty.set_size(rows, columns))
+            {
+                glib::g_warning!(G_LOG_DOMAIN, "failed to resize the pty: {}", e);
+            }
+        }
+        fn on_current_file_uri_changed(&self) {
+            let obj = self.obj();
+            let Some(report) = obj.current_file_uri().and_then(|uri| Report::parse(&uri)) else {
+                return;
+            };
+            let (column, row) = obj.cursor_position();
+            match report {
+                Report::Prompt {
+                    commands,
+                    lines,
+                    status,
+                } => self.prompt_shown((row, column), lines, commands, status),
+                Report::Command { commands } => self.command_started(commands),
+            }
+        }
+        /// the prompt ends at `position`, `lines` rows above
+        fn prompt_shown(
+            &self,
+            position: (i64, i64),
+            lines: u32,
+            commands: u64,
+            status: Option<i32>,
+        ) {
+            let obj = self.obj();
+            let prompt_row = position.0 - (lines as i64 - 1);
+            let ran = self.commands_run.replace(commands) < commands;
+            let mut records = self.commands.borrow_mut();
+            let mut finished = false;
+            if let Some(command) = records.last_mut().filter(|_| ran) {
+                // the start of quick commands may come along with their end
+                if command.output_start.is_none() {
+                    let (output_start, text) = self.command_line_end(command.command_start);
+                    command.output_start = Some(output_start);
+                    command.command = text;
+                }
+                command.output_end = Some((prompt_row, 0));
+                command.status = status;
+                if let Some(started) = command.started {
+                    command.duration = Some(started.elapsed());
+                    finished = true;
+                }
+            }
+            // forget the commands which went out of the scrollback
+            if let Some(adjustment) = obj.vadjustment() {
+                let first_row = adjustment.lower() as i64;
+                records.retain(|command| command.prompt.0 >= first_row);
+            }
+            records.push(CommandRecord {
+                prompt: (prompt_row, 0),
+                command_start: position,
+                output_start: None,
+                output_end: None,
+                command: None,
+                started: None,
+                duration: None,
+                status: None,
+            });
+            drop(records);
+            if finished {
+                obj.emit_by_name::<()>("command-finished", &[]);
+            }
+        }
+        fn command_started(&self, commands: u64) {
+            self.commands_run.set(commands);
+            let mut records = self.commands.borrow_mut();
+            let Some(command) = records
+                .last_mut()
+                .filter(|command| command.started.is_none())
+            else {
+                return;
+            };
+            let (output_start, text) = self.command_line_end(command.command_start);
+            command.output_start = Some(output_start);
+            command.command = text;
+            command.started = Some(Instant::now());
+        }
+        /// start of the row following the command line starting at `start`,
+        /// with the command, the output being possibly printed already
+        fn command_line_end(&self, start: (i64, i64)) -> ((i64, i64), Option<String>) {
+            let obj = self.obj();
+            let (_, cursor_row) = obj.cursor_position();
+            // VTE ends the rows with a newline unless the line wraps to the next one
+            let mut row = start.0;
+            while row < cursor_row
+                && !obj
+                    .text_between((row, 0), (row + 1, 0))
+                    .is_some_and(|text| text.contains('\n'))
+            {
+                row += 1;
+            }
+            let end = (row + 1, 0);
+            let command = obj
+                .text_between(start, end)
+                .map(|text| text.trim().to_string())
+                .filter(|text| !text.is_empty());
+            (end, command)
+        }
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for LayerConsoleTerminal {
+        const NAME: &'static str = "LayerConsoleTerminal";
+        type Type = super::LayerConsoleTerminal;
+        type ParentType = vte4::Terminal;
+    }
+
+    impl ObjectImpl for LayerConsoleTerminal {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            SIGNALS.get_or_init(|| vec![Signal::builder("command-finished").build()])
+        }
+        fn constructed(&self) {
+            self.parent_constructed();
+            let obj = self.obj();
+            obj.connect_current_file_uri_changed(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| this.on_current_file_uri_changed()
+            ));
+            // the selected output goes away with any other selection
+            obj.connect_selection_changed(|terminal| {
+                if terminal.has_selection() {
+                    terminal.unselect_output();
+                }
+            });
+        }
+    }
+    impl WidgetImpl for LayerConsoleTerminal {
+        fn snapshot(&self, snapshot: &gtk::Snapshot) {
+            self.parent_snapshot(snapshot);
+            let Some((start, end)) = self.selected_output.get() else {
+                return;
+            };
+            let obj = self.obj();
+            let top = obj
+                .vadjustment()
+                .map(|adjustment| adjustment.value())
+                .unwrap_or(0.0);
+            let char_height = obj.char_height() as f64;
+            let y = (start as f64 - top) * char_height;
+            let height = (end - start) as f64 * char_height;
+            let width = (obj.column_count() * obj.char_width()) as f64;
+            snapshot.append_color(
+                &gdk::RGBA::new(0.5, 0.5, 0.5, 0.4),
+                &graphene::Rect::new(0.0, y as f32, width as f32, height as f32),
+            );
+        }
+    }
+    impl TerminalImpl for LayerConsoleTerminal {
+        fn commit(&self, text: &str) {
+            self.parent_commit(text);
+            self.obj().unselect_output();
+            if let Some(handler) = self.input_handler.borrow().as_ref() {
+                handler(text.as_bytes());
+            }
+        }
+    }
+}
+
+glib::wrapper! {
+    pub struct LayerConsoleTerminal(ObjectSubclass<imp::LayerConsoleTerminal>)
+        @extends vte4::Terminal, gtk::Widget,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget, gtk::Scrollable;
+}
+
+impl Default for LayerConsoleTerminal {
+    fn default() -> Self {
+        glib::Object::new()
+    }
+}
+
+impl LayerConsoleTerminal {
+    /// run `args` in `directory`, `env` being `NAME=VALUE` variables added to ours,
+    /// as a login shell with `login_shell`
+    pub fn spawn(&self, directory: Option<&str>, args: &[&str], env: &[&str], login_shell: bool) {
+        self.imp().spawn(directory, args, env, login_shell);
+    }
+    /// run `args` in the daemon, so that it outlives the console, here if
+    /// the daemon can't
+    pub fn spawn_in_daemon(
+        &self,
+        directory: Option<&str>,
+        args: &[&str],
+        env: &[&str],
+        login_shell: bool,
+    ) {
+        let imp = self.imp();
+        imp.command
+            .replace(args.iter().map(|arg| arg.to_string()).collect());
+        imp.env
+            .replace(env.iter().map(|var| var.to_string()).collect());
+        imp.login_shell.set(login_shell);
+        let directory = directory.map(str::to_string);
+        let terminal = self.downgrade();
+        daemon::spawn(
+            args,
+            directory.as_deref(),
+            env,
+            login_shell,
+            move |session| {
+                let Some(this) = terminal.upgrade() else {
+                    // the tab closed meanwhile
+                    if let Ok(session) = session {
+                        session.client.close();
+                    }
+                    return;
+                };
+                match session {
+                    Ok(session) => this.imp().open_daemon_session(session),
+                    Err(e) => {
+                        glib::g_warning!(G_LOG_DOMAIN, "can't run the tab in the daemon: {}", e);
+                        let command = this.command();
+                        let env = this.env();
+                        this.spawn(
+                            directory.as_deref(),
+                            &command.iter().map(String::as_str).collect::<Vec<_>>(),
+                            &env.iter().map(String::as_str).collect::<Vec<_>>(),
+                            login_shell,
+                        );
+                    }
+                }
+            },
+        );
+    }
+    /// follow the process of the daemon left by a previous console,
+    /// `on_error` being called if it can't
+    pub fn attach<F: FnOnce(&Self, String) + 'static>(&self, id: u64, on_error: F) {
+        let terminal = self.downgrade();
+        daemon::attach(id, move |session| {
+            let Some(this) = terminal.upgrade() else {
+                return;
+            };
+            match session {
+                Ok(session) => this.imp().open_daemon_session(session),
+                Err(e) => on_error(&this, e),
+            }
+        });
+    }
+    /// the session of the process in the daemon, if it runs there
+    pub fn daemon_id(&self) -> Option<u64> {
+        self.imp()
+            .daemon_client
+            .borrow()
+            .as_ref()
+            .map(daemon::Client::id)
+    }
+    /// end the process of the daemon rather than keep it for the next console
+    pub fn close_daemon_session(&self) {
+        if let Some(client) = self.imp().daemon_client.take() {
+            client.close();
+        }
+    }
+    pub fn command(&self) -> Vec<String> {
+        self.imp().command.borrow().clone()
+    }
+    pub fn env(&self) -> Vec<String> {
+        self.imp().env.borrow().clone()
+    }
+    pub fn login_shell(&self) -> bool {
+        self.imp().login_shell.get()
+    }
+    pub fn tab_title(&self) -> Option<String> {
+        self.imp().tab_title.borrow().clone()
+    }
+    pub fn set_tab_title(&self, title: Option<String>) {
+        self.imp().tab_title.replace(title);
+    }
+    pub fn title(&self) -> Option<String> {
+        self.window_title()
+            .map(|t| t.to_string())
+            .filter(|t| !t.is_empty())
+    }
+    /// text of the tab: its own title, the terminal's or the program running
+    pub fn label(&self) -> String {
+        self.tab_title()
+            .or_else(|| self.title())
+            .or_else(|| {
+                let command = self.imp().command.borrow();
+                let program = command.first()?;
+                Some(program.rsplit('/').next().unwrap_or(program).to_string())
+            })
+            .unwrap_or_default()
+    }
+    /// remember the new title of the terminal, returning the previous one
+    /// with how long it was shown
+    pub fn track_title(&self) -> Option<(Option<String>, Duration)> {
+        let imp = self.imp();
+        let previous_title = imp.last_title.replace(self.title());
+        let now = Instant::now();
+        let changed_at = imp.title_changed_at.replace(Some(now))?;
+        Some((previous_title, now - changed_at))
+    }
+    /// set the title of the terminal until the program sets its own
+    pub fn restore_title(&self, title: &str) {
+        let title = title.replace(|c: char| c.is_control(), "");
+        self.feed(format!("\x1b]2;{}\x07", title).as_bytes());
+    }
+    /// directory reported by the shell through OSC 7
+    pub fn current_directory(&self) -> Option<String> {
+        let uri = self.current_directory_uri()?;
+        match glib::filename_from_uri(&uri) {
+            Ok((path, _hostname)) => Some(path.to_string_lossy().to_string()),
+            Err(e) => {
+                glib::g_warning!(
+                    G_LOG_DOMAIN,
+                    "invalid current directory uri ({}): {}",
+                    uri,
+                    e
+                );
+                None
+            }
+        }
+    }
+    /// text of the scrollback and the screen, cut to the last lines
+    /// fitting in `limit` bytes
+    pub fn scrollback_text(&self, limit: usize) -> Option<String> {
+        let stream = gio::MemoryOutputStream::new_resizable();
+        let written = self
+            .write_contents_sync(&stream, vte4::WriteFlags::Default, gio::Cancellable::NONE)
+            .and_then(|_| stream.close(gio::Cancellable::NONE));
+        if let Err(e) = written {
+            glib::g_warning!(G_LOG_DOMAIN, "failed to get the scrollback: {}", e);
+            return None;
+        }
+        let bytes = stream.steal_as_bytes();
+        let text = String::from_utf8_lossy(&bytes);
+        let text = text.trim_end();
+        let mut start = text.len().saturating_sub(limit);
+        if start > 0 {
+            while !text.is_char_boundary(start) {
+                start += 1;
+            }
+            start += text[start..].find('\n')? + 1;
+        }
+        Some(text[start..].to_string()).filter(|text| !text.is_empty())
+    }
+    /// show the scrollback of a previous session, dimmed and followed
+    /// by a separator, above what the new process prints
+    pub fn replay_scrollback(&self, text: &str, saved: Option<&glib::DateTime>) {
+        let mut data = String::from("\x1b[2m");
+        for line in text.lines() {
+            data.extend(line.chars().filter(|&c| c == '\t' || !c.is_control()));
+            data.push_str("\r\n");
+        }
+        data.push_str("\x1b[0m\x1b[7m restored session");
+        if let Some(date) = saved.and_then(|saved| saved.format("%c").ok()) {
+            data.push_str(&format!(" from {}", date));
+        }
+        data.push_str(" \x1b[0m\r\n");
+        self.feed(data.as_bytes());
+    }
+    /// file of the scrollback replayed, kept in the session until the next
+    /// quit saves the scrollback again
+    pub fn restored_scrollback(&self) -> Option<PathBuf> {
+        self.imp().restored_scrollback.borrow().clone()
+    }
+    pub fn set_restored_scrollback(&self, path: Option<PathBuf>) {
+        self.imp().restored_scrollback.replace(path);
+    }
+    /// detect `regex` in the text, telling it apart by `kind`
+    pub fn add_match(&self, regex: &vte4::Regex, kind: MatchKind) {
+        let tag = self.match_add_regex(regex, 0);
+        self.match_set_cursor_name(tag, "pointer");
+        self.imp().match_kinds.borrow_mut().insert(tag, kind);
+    }
+    pub fn clear_matches(&self) {
+        self.match_remove_all();
+        self.imp().match_kinds.borrow_mut().clear();
+    }
+    /// text matched at `x`, `y` with what detected it
+    pub fn match_at(&self, x: f64, y: f64) -> Option<(glib::GString, MatchKind)> {
+        let (Some(text), tag) = self.check_match_at(x, y) else {
+            return None;
+        };
+        let kind = *self.imp().match_kinds.borrow().get(&tag)?;
+        Some((text, kind))
+    }
+    /// send the input to `f` rather than to a pty, the output of the
+    /// process being given with `feed_output`
+    pub fn set_input_handler<F: Fn(&[u8]) + 'static>(&self, f: F) {
+        self.imp().input_handler.replace(Some(Box::new(f)));
+    }
+    pub fn has_input_handler(&self) -> bool {
+        self.imp().input_handler.borrow().is_some()
+    }
+    /// whether the program asked for the mouse events, VTE showing
+    /// the arrow pointer instead of the text one meanwhile
+    pub fn tracks_mouse(&self) -> bool {
+        self.cursor()
+            .and_then(|cursor| cursor.name())
+            .is_some_and(|name| name == "default")
+    }
+    pub fn feed_output(&self, data: &[u8]) {
+        self.feed(data);
+    }
+    /// whether the shell sends prompt marks
+    pub fn has_shell_integration(&self) -> bool {
+        !self.imp().commands.borrow().is_empty()
+    }
+    pub fn last_command(&self) -> Option<CommandRecord> {
+        self.imp().commands.borrow().last().cloned()
+    }
+    /// top left corner of the cell at `row` of the screen and `column`, in
+    /// the coordinates of the terminal, which start inside its border and
+    /// padding where VTE draws the text
+    pub fn cell_origin(&self, row: i64, column: i64) -> (f64, f64) {
+        (
+            (column * self.char_width()) as f64,
+            (row * self.char_height()) as f64,
+        )
+    }
+    /// center of the cell at `row` of the screen and `column`, see `cell_origin`
+    pub fn cell_center(&self, row: i64, column: i64) -> (f64, f64) {
+        let (x, y) = self.cell_origin(row, column);
+        (
+            x + self.char_width() as f64 / 2.0,
+            y + self.char_height() as f64 / 2.0,
+        )
+    }
+    /// text of the absolute `row`, with the column of the cell each of its
+    /// bytes comes from, as wide characters take several cells
+    pub fn row_text(&self, row: i64) -> Option<(String, Vec<i64>)> {
+        let text = self.text_between((row, 0), (row, self.column_count()))?;
+        let text = text.trim_end_matches('\n').to_string();
+        let ambiguous_wide = self.cjk_ambiguous_width() == 2;
+        let mut column = 0;
+        let mut columns = Vec::with_capacity(text.len());
+        for c in text.chars() {
+            columns.extend(std::iter::repeat(column).take(c.len_utf8()));
+            // combining characters share the cell of the previous one
+            column += if c.is_zero_width() {
+                0
+            } else if c.is_wide() || (ambiguous_wide && c.is_wide_cjk()) {
+                2
+            } else {
+                1
+            };
+        }
+        Some((text, columns))
+    }
+    /// text from `start` up to `end` excluded
+    fn text_between(&self, start: (i64, i64), end: (i64, i64)) -> Option<String> {
+        let (text, _) = self.text_range_format(vte4::Format::Text, start.0, start.1, end.0, end.1);
+        text.map(|text| text.to_string())
+    }
+    /// scroll so that the previous or next prompt is at the top, false if there is none
+    pub fn scroll_to_prompt(&self, forward: bool) -> bool {
+        let Some(adjustment) = self.vadjustment() else {
+            return false;
+        };
+        let top = adjustment.value().round() as i64;
+        let commands = self.imp().commands.borrow();
+        let mut rows = commands.iter().map(|command| command.prompt.0);
+        let row = if forward {
+            rows.find(|&row| row > top)
+        } else {
+            rows.rev().find(|&row| row < top)
+        };
+        match row {
+            Some(row) => {
+                adjustment.set_value(row as f64);
+                true
+            }
+            None => false,
+        }
+    }
+    /// rows of the output of the command shown at `row`, or of the last
+    /// finished one, from the first up to the last excluded
+    fn command_output_rows(&self, row: Option<i64>) -> Option<(i64, i64)> {
+        let commands = self.imp().commands.borrow();
+        let command = match row {
+            Some(row) => commands
+                .iter()
+                .rev()
+                .find(|command| command.prompt.0 <= row)?,
+            None => commands
+                .iter()
+                .rev()
+                .find(|command| command.output_end.is_some())?,
+        };
+        let (start, _) = command.output_start?;
+        let end = match command.output_end {
+            Some((end, _)) => end,
+            // still running, take everything up to the cursor
+            None => self.cursor_position().1 + 1,
+        };
+        Some((start, end)).filter(|(start, end)| start < end)
+    }
+    /// output of the command shown at `row`, or of the last finished one
+    pub fn command_output(&self, row: Option<i64>) -> Option<String> {
+        let (start, end) = self.command_output_rows(row)?;
+        self.text_between((start, 0), (end, 0))
+            .map(|text| text.trim_end_matches('\n').to_string())
+    }
+    /// copy the output of the command shown at `row`, or of the last
+    /// finished one, to the clipboard and the primary selection, and
+    /// highlight it. VTE can't select a range itself, so the highlight is
+    /// drawn over the terminal and goes away with the next input or selection.
+    pub fn select_output(&self, row: Option<i64>) -> bool {
+        let Some(rows) = self.command_output_rows(row) else {
+            return false;
+        };
+        let Some(output) = self.command_output(row) else {
+            return false;
+        };
+        self.unselect_all();
+        self.clipboard().set_text(&output);
+        self.primary_clipboard().set_text(&output);
+        self.imp().selected_output.set(Some(rows));
+        self.queue_draw();
+        true
+    }
+    pub fn unselect_output(&self) {
+        if self.imp().selected_output.take().is_some() {
+            self.queue_draw();
+        }
+    }
+    /// text of the output selected with `select_output`
+    pub fn selected_output(&self) -> Option<String> {
+        let (start, end) = self.imp().selected_output.get()?;
+        self.text_between((start, 0), (end, 0))
+            .map(|text| text.trim_end_matches('\n').to_string())
+    }
+    pub fn connect_command_finished<F: Fn(&Self) + 'static>(&self, f: F) -> glib::SignalHandlerId {
+        self.connect_closure(
+            "command-finished",
+            false,
+            glib::closure_local!(move |terminal: &Self| f(terminal)),
+        )
+    }
+}
@@ -0,0 +1,669 @@
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::{Rc, Weak};
+
+use crate::G_LOG_DOMAIN;
+
+const READ_SIZE: usize = 64 * 1024;
+/// bytes typed in a pane sent by a single send-keys command
+const SEND_KEYS_CHUNK: usize = 256;
+
+// tmux in control mode (`tmux -C`, `-CC` being the same protocol wrapped for
+// a terminal) reads commands on its input, one per line, and prints each
+// reply between %begin and %end (or %error) lines. every other line is a
+// notification starting with %, such as the output of the panes.
+
+/// notification sent by tmux about the attached session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Output {
+        pane: u32,
+        data: Vec<u8>,
+    },
+    WindowAdd(u32),
+    WindowClose(u32),
+    WindowRenamed {
+        window: u32,
+        name: String,
+    },
+    LayoutChange {
+        window: u32,
+        layout: String,
+    },
+    /// the active pane of the window changed
+    WindowPaneChanged {
+        window: u32,
+        pane: u32,
+    },
+    /// the current window of the session changed
+    SessionWindowChanged(u32),
+    /// the client was detached or tmux exited, with the reason if given
+    Exit(Option<String>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Line {
+    Begin,
+    End,
+    Error,
+    Event(Event),
+    /// a notification we don't follow
+    Ignored,
+    /// a line of the reply to a command
+    Reply(String),
+}
+
+/// the number of `@3`, `%5` or `$1`, the ids of windows, panes and sessions
+pub fn parse_id(text: &str, sigil: char) -> Option<u32> {
+    text.strip_prefix(sigil)?.parse().ok()
+}
+
+/// undo the octal escapes of the characters tmux can't print as they are
+fn unescape(text: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let octal = text.get(i + 1..i + 4).filter(|digits| {
+            text[i] == b'\\' && digits.iter().all(|digit| (b'0'..=b'7').contains(digit))
+        });
+        match octal {
+            Some(digits) => {
+                let value = digits
+                    .iter()
+                    .fold(0u32, |value, digit| value * 8 + u32::from(digit - b'0'));
+                data.push(value as u8);
+                i += 4;
+            }
+            None => {
+                data.push(text[i]);
+                i += 1;
+            }
+        }
+    }
+    data
+}
+
+fn parse_line(line: &[u8], in_reply: bool) -> Line {
+    let text = String::from_utf8_lossy(line);
+    let mut fields = text.splitn(2, ' ');
+    let name = fields.next().unwrap_or_default();
+    let rest = fields.next().unwrap_or_default();
+    match name {
+        "%begin" => return Line::Begin,
+        "%end" if in_reply => return Line::End,
+        "%error" if in_reply => return Line::Error,
+        _ if in_reply => return Line::Reply(text.into_owned()),
+        _ => (),
+    }
+    let mut args = rest.splitn(2, ' ');
+    let first = args.next().unwrap_or_default();
+    let second = args.next().unwrap_or_default();
+    let event = match name {
+        "%output" => {
+            // the data may not be text, take it from the raw line
+            let Some(pane) = parse_id(first, '%') else {
+                return Line::Ignored;
+            };
+            let start = (name.len() + first.len() + 2).min(line.len());
+            Some(Event::Output {
+                pane,
+                data: unescape(&line[start..]),
+            })
+        }
+        "%window-add" => parse_id(first, '@').map(Event::WindowAdd),
+        "%window-close" | "%unlinked-window-close" => parse_id(first, '@').map(Event::WindowClose),
+        "%window-renamed" => parse_id(first, '@').map(|window| Event::WindowRenamed {
+            window,
+            name: second.to_string(),
+        }),
+        "%layout-change" => parse_id(first, '@').map(|window| Event::LayoutChange {
+            window,
+            layout: second.split(' ').next().unwrap_or_default().to_string(),
+        }),
+        "%window-pane-changed" => parse_id(first, '@')
+            .zip(parse_id(second, '%'))
+            .map(|(window, pane)| Event::WindowPaneChanged { window, pane }),
+        "%session-window-changed" => parse_id(second, '@').map(Event::SessionWindowChanged),
+        "%exit" => Some(Event::Exit((!rest.is_empty()).then(|| rest.to_string()))),
+        _ => None,
+    };
+    event.map_or(Line::Ignored, Line::Event)
+}
+
+/// how the panes of a window are arranged, sizes being in cells
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Layout {
+    Pane {
+        id: u32,
+        columns: i64,
+        rows: i64,
+    },
+    /// cells side by side when `horizontal`, stacked otherwise
+    Split {
+        horizontal: bool,
+        children: Vec<Layout>,
+    },
+}
+
+impl Layout {
+    /// parse a layout as printed by `#{window_layout}`,
+    /// like `b25f,160x48,0,0{80x48,0,0,1,79x48,81,0,2}`
+    pub fn parse(text: &str) -> Option<Layout> {
+        // skip the checksum
+        let (_, cells) = text.split_once(',')?;
+        let mut parser = LayoutParser {
+            text: cells.as_bytes(),
+            at: 0,
+        };
+        let layout = parser.cell()?;
+        (parser.at == parser.text.len()).then_some(layout)
+    }
+    /// ids of the panes, from the top left
+    pub fn panes(&self) -> Vec<u32> {
+        match self {
+            Layout::Pane { id, .. } => vec![*id],
+            Layout::Split { children, .. } => children.iter().flat_map(Layout::panes).collect(),
+        }
+    }
+}
+
+struct LayoutParser<'a> {
+    text: &'a [u8],
+    at: usize,
+}
+
+impl LayoutParser<'_> {
+    fn number(&mut self) -> Option<i64> {
+        let digits = self.text[self.at..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        let number = std::str::from_utf8(&self.text[self.at..self.at + digits])
+            .ok()?
+            .parse()
+            .ok()?;
+        self.at += digits;
+        Some(number)
+    }
+    fn expect(&mut self, c: u8) -> Option<()> {
+        (self.text.get(self.at) == Some(&c)).then(|| self.at += 1)
+    }
+    /// `WxH,X,Y` followed by `,ID` for a pane or its cells within `{}` or `[]`
+    fn cell(&mut self) -> Option<Layout> {
+        let columns = self.number()?;
+        self.expect(b'x')?;
+        let rows = self.number()?;
+        self.expect(b',')?;
+        self.number()?;
+        self.expect(b',')?;
+        self.number()?;
+        let open = *self.text.get(self.at)?;
+        self.at += 1;
+        let close = match open {
+            b',' => {
+                let id = self.number()?.try_into().ok()?;
+                return Some(Layout::Pane { id, columns, rows });
+            }
+            b'{' => b'}',
+            b'[' => b']',
+            _ => return None,
+        };
+        let mut children = vec![self.cell()?];
+        while self.expect(b',').is_some() {
+            children.push(self.cell()?);
+        }
+        self.expect(close)?;
+        Some(Layout::Split {
+            horizontal: open == b'{',
+            children,
+        })
+    }
+}
+
+type Reply = Box<dyn FnOnce(Result<Vec<String>, String>)>;
+
+/// tmux running in control mode, attached to a session
+pub struct Controller {
+    session: String,
+    process: gio::Subprocess,
+    stdin: Rc<Writer>,
+    /// called with the replies of the commands sent, in order
+    pending: RefCell<VecDeque<Reply>>,
+    /// lines of the reply being received
+    reply: RefCell<Option<Vec<String>>>,
+}
+
+impl std::fmt::Debug for Controller {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Controller")
+            .field("session", &self.session)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Controller {
+    /// attach to `session`, created if it doesn't exist, `on_event` being
+    /// called with its notifications
+    pub fn start<F: Fn(Event) + 'static>(session: &str, on_event: F) -> Result<Rc<Self>, String> {
+        let process = gio::Subprocess::newv(
+            &[
+                "tmux".as_ref(),
+                "-C".as_ref(),
+                "new-session".as_ref(),
+                "-A".as_ref(),
+                "-s".as_ref(),
+                std::ffi::OsStr::new(session),
+            ],
+            gio::SubprocessFlags::STDIN_PIPE | gio::SubprocessFlags::STDOUT_PIPE,
+        )
+        .map_err(|e| format!("can't run tmux: {}", e))?;
+        let stdin = process.stdin_pipe().ok_or("no pipe to tmux")?;
+        let stdout = process.stdout_pipe().ok_or("no pipe from tmux")?;
+        let controller = Rc::new(Controller {
+            session: session.to_string(),
+            process,
+            stdin: Rc::new(Writer {
+                stream: stdin,
+                queue: RefCell::default(),
+                writing: Cell::new(false),
+                closing: Cell::new(false),
+            }),
+            // tmux replies to the command it was started with first
+            pending: RefCell::new(VecDeque::from([Box::new(|_| ()) as Reply])),
+            reply: RefCell::default(),
+        });
+        read_lines(
+            Rc::downgrade(&controller),
+            stdout,
+            Vec::new(),
+            Rc::new(on_event),
+        );
+        Ok(controller)
+    }
+    /// run `command`, `f` being called with the lines it printed or its error
+    pub fn command<F: FnOnce(Result<Vec<String>, String>) + 'static>(&self, command: &str, f: F) {
+        self.pending.borrow_mut().push_back(Box::new(f));
+        self.stdin.write(format!("{}\n", command).as_bytes());
+    }
+    /// run `command`, only telling when it fails
+    pub fn run(&self, command: &str) {
+        let name = command.split(' ').next().unwrap_or_default().to_string();
+        self.command(command, move |result| {
+            if let Err(e) = result {
+                glib::g_warning!(G_LOG_DOMAIN, "tmux {} failed: {}", name, e);
+            }
+        });
+    }
+    /// type `data` in `pane`
+    pub fn send_keys(&self, pane: u32, data: &[u8]) {
+        for chunk in data.chunks(SEND_KEYS_CHUNK) {
+            let hex = chunk
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+            self.run(&format!("send-keys -t %{} -H {}", pane, hex));
+        }
+    }
+    /// size the windows of the session for a client of `columns` x `rows` cells
+    pub fn set_size(&self, columns: i64, rows: i64) {
+        self.run(&format!("refresh-client -C {}x{}", columns, rows));
+    }
+    fn on_line(&self, line: &[u8], on_event: &dyn Fn(Event)) {
+        let in_reply = self.reply.borrow().is_some();
+        match parse_line(line, in_reply) {
+            Line::Begin => {
+                self.reply.replace(Some(Vec::new()));
+            }
+            Line::Reply(text) => {
+                if let Some(reply) = self.reply.borrow_mut().as_mut() {
+                    reply.push(text);
+                }
+            }
+            line @ (Line::End | Line::Error) => {
+                let lines = self.reply.take().unwrap_or_default();
+                let result = if line == Line::End {
+                    Ok(lines)
+                } else {
+                    Err(lines.join("\n"))
+                };
+                let reply = self.pending.borrow_mut().pop_front();
+                if let Some(reply) = reply {
+                    reply(result);
+                }
+            }
+            Line::Event(event) => on_event(event),
+            Line::Ignored => (),
+        }
+    }
+}
+
+impl Drop for Controller {
+    /// tmux detaches once its input is closed
+    fn drop(&mut self) {
+        self.stdin.close();
+        // the reader tells the exit once tmux is gone
+        self.process.wait_async(gio::Cancellable::NONE, |_| ());
+    }
+}
+
+/// input of tmux, written without blocking the main loop in the order given
+struct Writer {
+    stream: gio::OutputStream,
+    /// bytes waiting for the write in progress to finish
+    queue: RefCell<Vec<u8>>,
+    writing: Cell<bool>,
+    /// close the stream once the queue is written
+    closing: Cell<bool>,
+}
+
+impl Writer {
+    fn write(self: &Rc<Self>, data: &[u8]) {
+        self.queue.borrow_mut().extend_from_slice(data);
+        if !self.writing.get() {
+            self.flush();
+        }
+    }
+    fn flush(self: &Rc<Self>) {
+        let data = self.queue.take();
+        if data.is_empty() {
+            self.writing.set(false);
+            if self.closing.get() {
+                self.close();
+            }
+            return;
+        }
+        self.writing.set(true);
+        let writer = self.clone();
+        self.stream.write_all_async(
+            data,
+            glib::Priority::DEFAULT,
+            gio::Cancellable::NONE,
+            move |result| match result {
+                Ok(_) => writer.flush(),
+                Err((_, e)) => {
+                    glib::g_warning!(G_LOG_DOMAIN, "failed to write to tmux: {}", e);
+                    writer.queue.borrow_mut().clear();
+                    writer.writing.set(false);
+                    if writer.closing.get() {
+                        writer.close();
+                    }
+                }
+            },
+        );
+    }
+    /// close the stream, once what is queued is written
+    fn close(&self) {
+        if self.writing.get() {
+            self.closing.set(true);
+        } else if let Err(e) = self.stream.close(gio::Cancellable::NONE) {
+            glib::g_warning!(G_LOG_DOMAIN, "failed to close the pipe to tmux: {}", e);
+        }
+    }
+}
+
+/// read what tmux prints line by line, which isn't always text
+fn read_lines(
+    controller: Weak<Controller>,
+    stdout: gio::InputStream,
+    mut buffer: Vec<u8>,
+    on_event: Rc<dyn Fn(Event)>,
+) {
+    stdout.clone().read_bytes_async(
+        READ_SIZE,
+        glib::Priority::DEFAULT,
+        gio::Cancellable::NONE,
+        move |result| match result {
+            Ok(bytes) if !bytes.is_empty() => {
+                // nobody listens anymore once the controller is dropped
+                let Some(this) = controller.upgrade() else {
+                    return;
+                };
+                buffer.extend_from_slice(&bytes);
+                while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+                    let line = buffer.drain(..=end).collect::<Vec<_>>();
+                    let line = &line[..end];
+                    this.on_line(line.strip_suffix(b"\r").unwrap_or(line), &*on_event);
+                }
+                read_lines(controller, stdout, buffer, on_event);
+            }
+            Ok(_) => on_event(Event::Exit(None)),
+            Err(e) => on_event(Event::Exit(Some(e.to_string()))),
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// lines printed by `tmux -C` with the state of the reply they come in
+    fn parse_lines(lines: &[&[u8]]) -> Vec<Line> {
+        let mut in_reply = false;
+        lines
+            .iter()
+            .map(|line| {
+                let line = parse_line(line, in_reply);
+                match line {
+                    Line::Begin => in_reply = true,
+                    Line::End | Line::Error => in_reply = false,
+                    _ => (),
+                }
+                line
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_reply() {
+        assert_eq!(
+            parse_lines(&[
+                b"%begin 1718000000 274 0",
+                b"%end 1718000000 274 0",
+                b"%begin 1718000000 275 1",
+                b"@1 1 bash",
+                b"@2 0 %end of the line",
+                b"%end 1718000000 275 1",
+                b"%begin 1718000000 276 1",
+                b"unknown command: frobnicate",
+                b"%error 1718000000 276 1",
+            ]),
+            [
+                Line::Begin,
+                Line::End,
+                Line::Begin,
+                Line::Reply("@1 1 bash".to_string()),
+                Line::Reply("@2 0 %end of the line".to_string()),
+                Line::End,
+                Line::Begin,
+                Line::Reply("unknown command: frobnicate".to_string()),
+                Line::Error,
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_notifications_in_reply() {
+        // tmux doesn't interleave them, whatever starts with % is the reply
+        assert_eq!(
+            parse_lines(&[
+                b"%begin 1718000000 280 1",
+                b"%output %1 hello",
+                b"%window-add @3",
+                b"%end 1718000000 280 1",
+            ]),
+            [
+                Line::Begin,
+                Line::Reply("%output %1 hello".to_string()),
+                Line::Reply("%window-add @3".to_string()),
+                Line::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_end_outside_reply() {
+        assert_eq!(parse_line(b"%end 1718000000 274 0", false), Line::Ignored);
+        assert_eq!(parse_line(b"%error 1718000000 274 0", false), Line::Ignored);
+    }
+
+    #[test]
+    fn parse_notifications() {
+        assert_eq!(
+            parse_lines(&[
+                b"%sessions-changed",
+                b"%session-changed $0 main",
+                b"%window-add @2",
+                b"%window-renamed @2 vim config.toml",
+                b"%layout-change @2 5e1c,160x48,0,0{80x48,0,0,3,79x48,81,0,4} 5e1c,160x48,0,0{80x48,0,0,3,79x48,81,0,4} *",
+                b"%window-pane-changed @2 %4",
+                b"%session-window-changed $0 @2",
+                b"%unlinked-window-close @5",
+                b"%window-close @2",
+                b"%exit",
+                b"%exit detached",
+            ]),
+            [
+                Line::Ignored,
+                Line::Ignored,
+                Line::Event(Event::WindowAdd(2)),
+                Line::Event(Event::WindowRenamed {
+                    window: 2,
+                    name: "vim config.toml".to_string(),
+                }),
+                Line::Event(Event::LayoutChange {
+                    window: 2,
+                    layout: "5e1c,160x48,0,0{80x48,0,0,3,79x48,81,0,4}".to_string(),
+                }),
+                Line::Event(Event::WindowPaneChanged { window: 2, pane: 4 }),
+                Line::Event(Event::SessionWindowChanged(2)),
+                Line::Event(Event::WindowClose(5)),
+                Line::Event(Event::WindowClose(2)),
+                Line::Event(Event::Exit(None)),
+                Line::Event(Event::Exit(Some("detached".to_string()))),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_bad_notifications() {
+        assert_eq!(parse_line(b"%window-add 2", false), Line::Ignored);
+        assert_eq!(parse_line(b"%output 1 hello", false), Line::Ignored);
+        assert_eq!(parse_line(b"%window-pane-changed @2", false), Line::Ignored);
+    }
+
+    #[test]
+    fn parse_output() {
+        assert_eq!(
+            parse_line(
+                b"%output %1 \\033]0;user@host:~\\007\\033[?2004huser@host:~$ ",
+                false
+            ),
+            Line::Event(Event::Output {
+                pane: 1,
+                data: b"\x1b]0;user@host:~\x07\x1b[?2004huser@host:~$ ".to_vec(),
+            })
+        );
+        assert_eq!(
+            parse_line(b"%output %12 ls\\015\\012", false),
+            Line::Event(Event::Output {
+                pane: 12,
+                data: b"ls\r\n".to_vec(),
+            })
+        );
+        // not UTF-8, as when a program prints binary data
+        assert_eq!(
+            parse_line(b"%output %1 \xff\xfe", false),
+            Line::Event(Event::Output {
+                pane: 1,
+                data: b"\xff\xfe".to_vec(),
+            })
+        );
+        assert_eq!(
+            parse_line(b"%output %1", false),
+            Line::Event(Event::Output {
+                pane: 1,
+                data: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn unescape_octal() {
+        assert_eq!(unescape(b"a\\134b"), b"a\\b");
+        assert_eq!(unescape(b"\\033[0m\\015\\012"), b"\x1b[0m\r\n");
+        assert_eq!(unescape(b"\\303\\251t\\303\\251"), "été".as_bytes());
+    }
+
+    #[test]
+    fn unescape_incomplete() {
+        assert_eq!(unescape(b"end\\"), b"end\\");
+        assert_eq!(unescape(b"end\\0"), b"end\\0");
+        assert_eq!(unescape(b"end\\01"), b"end\\01");
+        assert_eq!(unescape(b"\\8\\09x"), b"\\8\\09x");
+        assert_eq!(unescape(b""), b"");
+    }
+
+    fn pane(id: u32, columns: i64, rows: i64) -> Layout {
+        Layout::Pane { id, columns, rows }
+    }
+
+    #[test]
+    fn parse_single_pane() {
+        assert_eq!(Layout::parse("b25f,160x48,0,0,1"), Some(pane(1, 160, 48)));
+    }
+
+    #[test]
+    fn parse_nested_layout() {
+        let layout = Layout::parse(
+            "bb62,159x48,0,0{79x48,0,0,0,79x48,80,0[79x24,80,0,1,79x23,80,25{39x23,80,25,2,39x23,120,25,3}]}",
+        )
+        .unwrap();
+        assert_eq!(
+            layout,
+            Layout::Split {
+                horizontal: true,
+                children: vec![
+                    pane(0, 79, 48),
+                    Layout::Split {
+                        horizontal: false,
+                        children: vec![
+                            pane(1, 79, 24),
+                            Layout::Split {
+                                horizontal: true,
+                                children: vec![pane(2, 39, 23), pane(3, 39, 23)],
+                            },
+                        ],
+                    },
+                ],
+            }
+        );
+        assert_eq!(layout.panes(), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn parse_bad_layout() {
+        // trailing garbage
+        assert_eq!(Layout::parse("b25f,160x48,0,0,1 *"), None);
+        assert_eq!(
+            Layout::parse("5e1c,160x48,0,0{80x48,0,0,3,79x48,81,0,4}}"),
+            None
+        );
+        // unbalanced or mismatched brackets
+        assert_eq!(
+            Layout::parse("5e1c,160x48,0,0{80x48,0,0,3,79x48,81,0,4"),
+            None
+        );
+        assert_eq!(
+            Layout::parse("5e1c,160x48,0,0{80x48,0,0,3,79x48,81,0,4]"),
+            None
+        );
+        // no checksum, or missing fields
+        assert_eq!(Layout::parse("160x48,0,0,1"), None);
+        assert_eq!(Layout::parse("b25f,160x48,0,1"), None);
+        assert_eq!(Layout::parse("b25f,"), None);
+        assert_eq!(Layout::parse(""), None);
+    }
+}
@@ -1,3 +1,4 @@
+use gtk::glib;
 use nix::unistd::{Uid, User};
 
 pub fn get_user_shell() -> String {
@@ -8,3 +9,180 @@ pub fn get_user_shell() -> String {
         .to_string_lossy()
         .to_string()
 }
+
+/// the argv and flags spawning `command`. a login shell has an argv[0] made
+/// of `-` followed by the name of its program.
+pub fn spawn_args(command: &[&str], login_shell: bool) -> (Vec<String>, glib::SpawnFlags) {
+    match command.split_first() {
+        Some((program, args)) if login_shell => {
+            let name = std::path::Path::new(program)
+                .file_name()
+                .map_or(program.into(), |name| name.to_string_lossy());
+            let argv = [program.to_string(), format!("-{}", name)]
+                .into_iter()
+                .chain(args.iter().map(|arg| arg.to_string()))
+                .collect();
+            (
+                argv,
+                glib::SpawnFlags::SEARCH_PATH | glib::SpawnFlags::FILE_AND_ARGV_ZERO,
+            )
+        }
+        _ => (
+            command.iter().map(|arg| arg.to_string()).collect(),
+            glib::SpawnFlags::SEARCH_PATH,
+        ),
+    }
+}
+
+/// format a duration like `1h 2m 3s`, leaving out leading zero units
+pub fn format_duration(duration: std::time::Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {}m {}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// the value of the variable `name`, the XDG base directories and HOME
+/// getting their default when unset
+fn variable(name: &str) -> Option<String> {
+    if let Some(value) = std::env::var_os(name) {
+        return Some(value.to_string_lossy().to_string());
+    }
+    let dir = match name {
+        "HOME" => glib::home_dir(),
+        "XDG_CONFIG_HOME" => glib::user_config_dir(),
+        "XDG_DATA_HOME" => glib::user_data_dir(),
+        "XDG_STATE_HOME" => glib::user_state_dir(),
+        "XDG_CACHE_HOME" => glib::user_cache_dir(),
+        "XDG_RUNTIME_DIR" => glib::user_runtime_dir(),
+        _ => return None,
+    };
+    Some(dir.to_string_lossy().to_string())
+}
+
+/// `text` with a leading `~` replaced by the home directory and `$VAR` or
+/// `${VAR}` by the value of the variable, `$$` giving a `$`. the variables
+/// which are unset are left as they are and added to `unset`.
+pub fn expand_path(text: &str, unset: &mut Vec<String>) -> String {
+    let mut expanded = String::new();
+    let mut rest = text;
+    if rest == "~" || rest.starts_with("~/") {
+        expanded.push_str(&glib::home_dir().to_string_lossy());
+        rest = &rest[1..];
+    }
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, reference) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], &rest[start..start + end + 3]),
+                None => ("", &rest[start..start + 1]),
+            }
+        } else if let Some(after) = after.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+            continue;
+        } else {
+            let end = after
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(after.len());
+            (&after[..end], &rest[start..start + end + 1])
+        };
+        match variable(name) {
+            Some(value) if !name.is_empty() => expanded.push_str(&value),
+            _ => {
+                if !name.is_empty() && !unset.iter().any(|unset| unset == name) {
+                    unset.push(name.to_string());
+                }
+                expanded.push_str(reference);
+            }
+        }
+        rest = &rest[start + reference.len()..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(text: &str) -> (String, Vec<String>) {
+        let mut unset = Vec::new();
+        let expanded = expand_path(text, &mut unset);
+        (expanded, unset)
+    }
+
+    #[test]
+    fn expand_home() {
+        let home = glib::home_dir().to_string_lossy().to_string();
+        assert_eq!(expand("~"), (home.clone(), vec![]));
+        assert_eq!(expand("~/src"), (format!("{}/src", home), vec![]));
+        assert_eq!(expand("~user/src"), ("~user/src".to_string(), vec![]));
+        assert_eq!(expand("/tmp/~"), ("/tmp/~".to_string(), vec![]));
+    }
+
+    #[test]
+    fn expand_variables() {
+        std::env::set_var("LAYER_CONSOLE_TEST_DIR", "/srv/test");
+        assert_eq!(
+            expand("$LAYER_CONSOLE_TEST_DIR/a"),
+            ("/srv/test/a".to_string(), vec![])
+        );
+        assert_eq!(
+            expand("${LAYER_CONSOLE_TEST_DIR}a"),
+            ("/srv/testa".to_string(), vec![])
+        );
+        assert_eq!(
+            expand("$LAYER_CONSOLE_UNSET/a:${LAYER_CONSOLE_UNSET}"),
+            (
+                "$LAYER_CONSOLE_UNSET/a:${LAYER_CONSOLE_UNSET}".to_string(),
+                vec!["LAYER_CONSOLE_UNSET".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn expand_dollar() {
+        assert_eq!(expand("a$$b"), ("a$b".to_string(), vec![]));
+        assert_eq!(expand("$$HOME"), ("$HOME".to_string(), vec![]));
+        assert_eq!(expand("a$$$$"), ("a$$".to_string(), vec![]));
+    }
+
+    #[test]
+    fn expand_unclosed_brace() {
+        assert_eq!(expand("a${HOME"), ("a${HOME".to_string(), vec![]));
+        assert_eq!(expand("${"), ("${".to_string(), vec![]));
+        assert_eq!(expand("${}"), ("${}".to_string(), vec![]));
+    }
+
+    #[test]
+    fn expand_dollar_at_end() {
+        assert_eq!(expand("a$"), ("a$".to_string(), vec![]));
+        assert_eq!(expand("$"), ("$".to_string(), vec![]));
+        assert_eq!(expand("a$/b"), ("a$/b".to_string(), vec![]));
+    }
+
+    #[test]
+    fn expand_non_ascii() {
+        // not a variable name without braces
+        assert_eq!(expand("$été/a"), ("$été/a".to_string(), vec![]));
+        assert_eq!(
+            expand("${LAYER_CONSOLE_ÉTÉ}/a"),
+            (
+                "${LAYER_CONSOLE_ÉTÉ}/a".to_string(),
+                vec!["LAYER_CONSOLE_ÉTÉ".to_string()]
+            )
+        );
+        std::env::set_var("LAYER_CONSOLE_ÉTÉ_SET", "/été");
+        assert_eq!(
+            expand("/à/${LAYER_CONSOLE_ÉTÉ_SET}/ü"),
+            ("/à//été/ü".to_string(), vec![])
+        );
+    }
+}